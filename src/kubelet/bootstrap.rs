@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use kube::config::{AuthInfo, Context, KubeConfigOptions, Kubeconfig, NamedAuthInfo, NamedCluster, NamedContext};
+use kube::Config;
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::kubelet::{certificate, client};
+
+const CLIENT_SIGNER: &str = "kubernetes.io/kube-apiserver-client-kubelet";
+const CLIENT_CERT_PREFIX: &str = "kubelet-client";
+
+/// Config for talking to the API server. With `bootstrapKubeconfig` set, a
+/// client certificate is requested through a CSR unless `kubeconfig`
+/// already holds a valid one.
+pub async fn kube_config() -> anyhow::Result<Config> {
+    let config = config();
+    if config.bootstrap_kubeconfig.is_empty() {
+        return Ok(Config::infer().await?);
+    }
+    let kubeconfig = Path::new(&config.kubeconfig);
+    let cert_dir = Path::new(&config.cert_dir);
+    if kubeconfig.exists() && certificate::is_valid(&certificate::current_path(cert_dir, CLIENT_CERT_PREFIX)) {
+        info!("使用已有的kubeconfig {}", kubeconfig.display());
+    } else {
+        bootstrap(Path::new(&config.bootstrap_kubeconfig), kubeconfig, cert_dir).await?;
+    }
+    load(kubeconfig).await
+}
+
+/// Keeps the bootstrapped client certificate renewed. The node renews with
+/// its current identity, then reloads the kubeconfig so new clients, and
+/// the watchers of [`client::rotated`], use the new certificate.
+pub async fn rotate_client_certificate() {
+    let config = config();
+    if config.bootstrap_kubeconfig.is_empty() || !config.rotate_certificates {
+        return;
+    }
+    let cert_dir = Path::new(&config.cert_dir);
+    certificate::rotate(&certificate::current_path(cert_dir, CLIENT_CERT_PREFIX), || async {
+        request_client_certificate(client::client().await?, cert_dir).await?;
+        client::reload(load(Path::new(&config.kubeconfig)).await?);
+        info!("客户端证书已轮换");
+        Ok(())
+    })
+    .await;
+}
+
+async fn request_client_certificate(client: kube::Client, cert_dir: &Path) -> anyhow::Result<PathBuf> {
+    let params = certificate::node_params();
+    let (cert, key) =
+        certificate::request_certificate(client, CLIENT_SIGNER, params, &["digital signature", "client auth"]).await?;
+    Ok(certificate::store(cert_dir, CLIENT_CERT_PREFIX, &cert, &key).await?)
+}
+
+async fn load(path: &Path) -> anyhow::Result<Config> {
+    let kubeconfig = Kubeconfig::read_from(path)?;
+    Ok(Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default()).await?)
+}
+
+async fn bootstrap(bootstrap_path: &Path, kubeconfig_path: &Path, cert_dir: &Path) -> anyhow::Result<()> {
+    info!("使用 {} 进行TLS引导", bootstrap_path.display());
+    let bootstrap = Kubeconfig::read_from(bootstrap_path)?;
+    let client = client::from_config(load(bootstrap_path).await?)?;
+
+    let cert_path = request_client_certificate(client, cert_dir).await?;
+
+    let kubeconfig = client_kubeconfig(&bootstrap, &cert_path.to_string_lossy())?;
+    if let Some(dir) = kubeconfig_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(kubeconfig_path, serde_yaml::to_string(&kubeconfig)?).await?;
+    info!("已写入kubeconfig {}", kubeconfig_path.display());
+    Ok(())
+}
+
+/// A kubeconfig for the bootstrap kubeconfig's current cluster that
+/// authenticates with the issued certificate.
+fn client_kubeconfig(bootstrap: &Kubeconfig, cert_path: &str) -> anyhow::Result<Kubeconfig> {
+    let context_name = bootstrap
+        .current_context
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("bootstrap kubeconfig has no current context"))?;
+    let cluster_name = bootstrap
+        .contexts
+        .iter()
+        .find(|c| &c.name == context_name)
+        .and_then(|c| c.context.as_ref())
+        .map(|c| c.cluster.clone())
+        .ok_or_else(|| anyhow::anyhow!("context {} not found in bootstrap kubeconfig", context_name))?;
+    let cluster = bootstrap
+        .clusters
+        .iter()
+        .find(|c| c.name == cluster_name)
+        .and_then(|c| c.cluster.clone())
+        .ok_or_else(|| anyhow::anyhow!("cluster {} not found in bootstrap kubeconfig", cluster_name))?;
+    Ok(Kubeconfig {
+        clusters: vec![NamedCluster { name: "default-cluster".to_string(), cluster: Some(cluster) }],
+        auth_infos: vec![NamedAuthInfo {
+            name: "default-auth".to_string(),
+            auth_info: Some(AuthInfo {
+                client_certificate: Some(cert_path.to_string()),
+                client_key: Some(cert_path.to_string()),
+                ..Default::default()
+            }),
+        }],
+        contexts: vec![NamedContext {
+            name: "default-context".to_string(),
+            context: Some(Context {
+                cluster: "default-cluster".to_string(),
+                user: "default-auth".to_string(),
+                namespace: Some("default".to_string()),
+                extensions: None,
+            }),
+        }],
+        current_context: Some("default-context".to_string()),
+        kind: Some("Config".to_string()),
+        api_version: Some("v1".to_string()),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOTSTRAP: &str = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- name: other
+  cluster:
+    server: https://10.0.0.2:6443
+- name: kubernetes
+  cluster:
+    server: https://10.0.0.1:6443
+    certificate-authority-data: Y2EtZGF0YQ==
+contexts:
+- name: bootstrap
+  context:
+    cluster: kubernetes
+    user: tls-bootstrap-token-user
+current-context: bootstrap
+users:
+- name: tls-bootstrap-token-user
+  user:
+    token: abcdef.0123456789abcdef
+"#;
+
+    #[test]
+    fn builds_client_kubeconfig_for_the_current_cluster() {
+        let bootstrap = Kubeconfig::from_yaml(BOOTSTRAP).unwrap();
+        let cert_path = "/var/lib/kubelet/pki/kubelet-client-current.pem";
+        let kubeconfig = client_kubeconfig(&bootstrap, cert_path).unwrap();
+
+        assert_eq!(kubeconfig.clusters.len(), 1);
+        let cluster = kubeconfig.clusters[0].cluster.as_ref().unwrap();
+        assert_eq!(cluster.server.as_deref(), Some("https://10.0.0.1:6443"));
+        assert_eq!(cluster.certificate_authority_data.as_deref(), Some("Y2EtZGF0YQ=="));
+
+        // The bootstrap token is not carried over.
+        assert_eq!(kubeconfig.auth_infos.len(), 1);
+        let auth_info = kubeconfig.auth_infos[0].auth_info.as_ref().unwrap();
+        assert_eq!(auth_info.client_certificate.as_deref(), Some(cert_path));
+        assert_eq!(auth_info.client_key.as_deref(), Some(cert_path));
+        assert!(auth_info.token.is_none());
+
+        let context = kubeconfig.contexts[0].context.as_ref().unwrap();
+        assert_eq!(kubeconfig.current_context.as_deref(), Some(kubeconfig.contexts[0].name.as_str()));
+        assert_eq!(context.cluster, kubeconfig.clusters[0].name);
+        assert_eq!(context.user, kubeconfig.auth_infos[0].name);
+
+        // What gets written is read back unchanged.
+        let written = serde_yaml::to_string(&kubeconfig).unwrap();
+        let reread = Kubeconfig::from_yaml(&written).unwrap();
+        assert_eq!(reread.current_context, kubeconfig.current_context);
+        assert_eq!(reread.clusters[0].cluster.as_ref().unwrap().server.as_deref(), Some("https://10.0.0.1:6443"));
+    }
+
+    #[test]
+    fn rejects_bootstrap_kubeconfig_without_a_usable_context() {
+        let mut bootstrap = Kubeconfig::from_yaml(BOOTSTRAP).unwrap();
+        bootstrap.current_context = Some("missing".to_string());
+        assert!(client_kubeconfig(&bootstrap, "cert.pem").is_err());
+
+        bootstrap.current_context = None;
+        assert!(client_kubeconfig(&bootstrap, "cert.pem").is_err());
+
+        let mut bootstrap = Kubeconfig::from_yaml(BOOTSTRAP).unwrap();
+        bootstrap.clusters.retain(|c| c.name != "kubernetes");
+        assert!(client_kubeconfig(&bootstrap, "cert.pem").is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::Utc;
+use k8s_openapi::api::certificates::v1::{CertificateSigningRequest, CertificateSigningRequestSpec};
+use k8s_openapi::ByteString;
+use kube::api::{ObjectMeta, PostParams};
+use kube::Api;
+use rand::Rng;
+use tokio::io::AsyncWriteExt;
+use tracing::*;
+
+use crate::kubelet::minikubelet::NODE_NAME;
+
+/// How long to wait for a CSR to be approved and signed.
+const ISSUE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Wait before retrying a failed renewal.
+const RENEW_RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Subject `system:node:<name>` in `system:nodes`, which node certificates
+/// must carry to be approved.
+pub fn node_params() -> rcgen::CertificateParams {
+    let mut params = rcgen::CertificateParams::default();
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(rcgen::DnType::OrganizationName, "system:nodes");
+    params.distinguished_name.push(rcgen::DnType::CommonName, format!("system:node:{}", NODE_NAME));
+    params
+}
+
+/// Submits a CSR for `params` to `signer` and waits until it is issued,
+/// returning the certificate and its private key as PEM.
+pub async fn request_certificate(
+    client: kube::Client,
+    signer: &str,
+    params: rcgen::CertificateParams,
+    usages: &[&str],
+) -> anyhow::Result<(String, String)> {
+    let cert = rcgen::Certificate::from_params(params)?;
+    let csr = CertificateSigningRequest {
+        metadata: ObjectMeta { generate_name: Some("node-csr-".to_string()), ..Default::default() },
+        spec: CertificateSigningRequestSpec {
+            request: ByteString(cert.serialize_request_pem()?.into_bytes()),
+            signer_name: signer.to_string(),
+            usages: Some(usages.iter().map(|u| u.to_string()).collect()),
+            ..Default::default()
+        },
+        status: None,
+    };
+    let csrs: Api<CertificateSigningRequest> = Api::all(client);
+    let created = csrs.create(&PostParams::default(), &csr).await?;
+    let name = created.metadata.name.unwrap_or_default();
+    info!("已提交CSR {}, 等待批准: kubectl certificate approve {}", name, name);
+
+    let deadline = Instant::now() + ISSUE_TIMEOUT;
+    loop {
+        let status = csrs.get(&name).await?.status.unwrap_or_default();
+        for condition in status.conditions.iter().flatten() {
+            if (condition.type_ == "Denied" || condition.type_ == "Failed") && condition.status == "True" {
+                anyhow::bail!(
+                    "CSR {} {}: {}",
+                    name,
+                    condition.type_.to_lowercase(),
+                    condition.message.clone().unwrap_or_default()
+                );
+            }
+        }
+        if let Some(certificate) = status.certificate.filter(|c| !c.0.is_empty()) {
+            info!("CSR {} 已签发", name);
+            return Ok((String::from_utf8(certificate.0)?, cert.serialize_private_key_pem()));
+        }
+        if Instant::now() > deadline {
+            anyhow::bail!("timed out waiting for CSR {} to be issued", name);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// `<dir>/<prefix>-current.pem`, the link to the certificate in use.
+pub fn current_path(dir: &Path, prefix: &str) -> PathBuf {
+    dir.join(format!("{}-current.pem", prefix))
+}
+
+/// Writes certificate and key to a new timestamped file and repoints
+/// `<prefix>-current.pem` at it, returning the link.
+pub async fn store(dir: &Path, prefix: &str, cert_pem: &str, key_pem: &str) -> std::io::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let file_name = format!("{}-{}.pem", prefix, Utc::now().format("%Y-%m-%d-%H-%M-%S"));
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(dir.join(&file_name))
+        .await?;
+    file.write_all(cert_pem.as_bytes()).await?;
+    file.write_all(key_pem.as_bytes()).await?;
+    file.sync_all().await?;
+
+    // Swap the link with a rename so readers never see it missing.
+    let current = current_path(dir, prefix);
+    let tmp = dir.join(format!("{}-current.pem.tmp", prefix));
+    let _ = tokio::fs::remove_file(&tmp).await;
+    tokio::fs::symlink(&file_name, &tmp).await?;
+    tokio::fs::rename(&tmp, &current).await?;
+    Ok(current)
+}
+
+/// `(notBefore, notAfter)` of the first certificate in the PEM file at `path`.
+fn validity(path: &Path) -> Option<(SystemTime, SystemTime)> {
+    let pem = std::fs::read(path).ok()?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem).ok()?;
+    let cert = pem.parse_x509().ok()?;
+    let seconds = |t: x509_parser::time::ASN1Time| UNIX_EPOCH + Duration::from_secs(t.timestamp().max(0) as u64);
+    Some((seconds(cert.validity().not_before), seconds(cert.validity().not_after)))
+}
+
+/// Whether the first certificate in the PEM file at `path` is within its
+/// validity period.
+pub fn is_valid(path: &Path) -> bool {
+    let now = SystemTime::now();
+    validity(path).is_some_and(|(not_before, not_after)| not_before <= now && now < not_after)
+}
+
+/// A random point 70-90% into the certificate's lifetime, so a fleet of
+/// nodes does not renew at once. `None` if there is no readable certificate.
+fn rotation_deadline(path: &Path) -> Option<SystemTime> {
+    let (not_before, not_after) = validity(path)?;
+    Some(lifetime_point(not_before, not_after, rand::thread_rng().gen_range(0.7..0.9)))
+}
+
+/// The point `fraction` of the way from `not_before` to `not_after`.
+fn lifetime_point(not_before: SystemTime, not_after: SystemTime, fraction: f64) -> SystemTime {
+    let lifetime = not_after.duration_since(not_before).unwrap_or_default();
+    not_before + lifetime.mul_f64(fraction)
+}
+
+/// Calls `renew` whenever the certificate at `current` reaches its rotation
+/// deadline, straight away if it is missing, retrying failed renewals.
+pub async fn rotate<F, Fut>(current: &Path, mut renew: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    loop {
+        if let Some(deadline) = rotation_deadline(current) {
+            let wait = deadline.duration_since(SystemTime::now()).unwrap_or_default();
+            info!("证书 {} 将在 {:?} 后轮换", current.display(), wait);
+            tokio::time::sleep(wait).await;
+        }
+        if let Err(e) = renew().await {
+            warn!("Unable to renew certificate {}: {}", current.display(), e);
+            tokio::time::sleep(RENEW_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Writes a certificate valid for the ten days from 2024-01-01.
+    fn write_certificate(path: &Path) -> SystemTime {
+        let mut params = node_params();
+        params.not_before = rcgen::date_time_ymd(2024, 1, 1);
+        params.not_after = rcgen::date_time_ymd(2024, 1, 11);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        std::fs::write(path, cert.serialize_pem().unwrap() + &cert.serialize_private_key_pem()).unwrap();
+        UNIX_EPOCH + Duration::from_secs(1704067200)
+    }
+
+    #[test]
+    fn picks_points_in_the_lifetime() {
+        let not_before = UNIX_EPOCH + 100 * DAY;
+        let not_after = not_before + 10 * DAY;
+        assert_eq!(lifetime_point(not_before, not_after, 0.7), not_before + 7 * DAY);
+        assert_eq!(lifetime_point(not_before, not_after, 0.9), not_before + 9 * DAY);
+        // An inverted validity has no lifetime to spread over.
+        assert_eq!(lifetime_point(not_after, not_before, 0.8), not_after);
+    }
+
+    #[test]
+    fn rotation_deadline_is_70_to_90_percent_into_the_lifetime() {
+        let path = std::env::temp_dir().join(format!("rust-kubelet-cert-{}.pem", std::process::id()));
+        let not_before = write_certificate(&path);
+        assert_eq!(validity(&path), Some((not_before, not_before + 10 * DAY)));
+        assert!(!is_valid(&path));
+        for _ in 0..100 {
+            let deadline = rotation_deadline(&path).unwrap();
+            assert!(deadline >= not_before + 7 * DAY && deadline < not_before + 9 * DAY, "{:?}", deadline);
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(rotation_deadline(&path), None);
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use kube::client::ClientBuilder;
+use kube::{Client, Config};
+use tokio::sync::watch;
+
+use crate::metrics::instrumented::ApiServerMetricsLayer;
+
+static CONFIG: RwLock<Option<Config>> = RwLock::new(None);
+static ROTATIONS: OnceLock<watch::Sender<()>> = OnceLock::new();
+static CLIENT: Mutex<Option<CachedClient>> = Mutex::new(None);
+
+/// The client [`client`] hands out, valid until `rotated` fires.
+struct CachedClient {
+    client: Client,
+    rotated: watch::Receiver<()>,
+}
+
+fn rotations() -> &'static watch::Sender<()> {
+    ROTATIONS.get_or_init(|| watch::channel(()).0)
+}
+
+/// Sets the config [`client`] connects with, normally the one from TLS bootstrapping.
+pub fn init(config: Config) {
+    *CONFIG.write().unwrap() = Some(config);
+    *CLIENT.lock().unwrap() = None;
+}
+
+/// Swaps in a config with a renewed client certificate and tells
+/// [`rotated`] subscribers to reconnect.
+pub fn reload(config: Config) {
+    init(config);
+    rotations().send_replace(());
+}
+
+/// Notified whenever [`reload`] swaps the config. Clients built before then
+/// keep presenting the old certificate, so long-lived ones rebuild on it.
+pub fn rotated() -> watch::Receiver<()> {
+    rotations().subscribe()
+}
+
+/// API server client whose requests are counted on `/metrics`.
+pub fn from_config(config: Config) -> kube::Result<Client> {
+    let metrics = ApiServerMetricsLayer::new(&config.cluster_url);
+    Ok(ClientBuilder::try_from(config)?.with_layer(&metrics).build())
+}
+
+/// Client for the current config set by [`init`] or [`reload`], inferred
+/// like `Client::try_default` until then. It is built once and shared, and
+/// rebuilt after a rotation so the renewed certificate takes effect.
+pub async fn client() -> anyhow::Result<Client> {
+    if let Some(cached) = CLIENT.lock().unwrap().as_ref() {
+        if !cached.rotated.has_changed().unwrap_or(true) {
+            return Ok(cached.client.clone());
+        }
+    }
+    // Subscribe before reading the config, so a reload in between is seen
+    // on the next call.
+    let rotated = rotated();
+    let config = CONFIG.read().unwrap().clone();
+    let config = match config {
+        Some(config) => config,
+        None => Config::infer().await?,
+    };
+    let client = from_config(config)?;
+    *CLIENT.lock().unwrap() = Some(CachedClient { client: client.clone(), rotated });
+    Ok(client)
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+const CONFIG_ENV: &str = "KUBELET_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "/var/lib/kubelet/config.yaml";
+
+static CONFIG: OnceLock<KubeletConfig> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CgroupDriver {
+    Cgroupfs,
+    Systemd,
+}
+
+/// Host ID range that user-namespaced pods are allocated from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserNamespacePool {
+    #[serde(rename = "firstID")]
+    pub first_id: u32,
+    pub length: u32,
+}
+
+/// Client certificate authentication for the kubelet API.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct X509Authentication {
+    /// CA bundle client certificates are verified against; the cluster CA
+    /// from the kubeconfig is used when empty.
+    #[serde(rename = "clientCAFile")]
+    pub client_ca_file: String,
+    /// Serve without verifying client certificates when no CA is available.
+    /// The kubelet refuses to start in that case otherwise.
+    #[serde(rename = "allowMissingClientCA")]
+    pub allow_missing_client_ca: bool,
+}
+
+/// Bearer token authentication through `TokenReview`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookAuthentication {
+    pub enabled: bool,
+    /// How long token review results are cached, as a Go duration.
+    #[serde(rename = "cacheTTL")]
+    pub cache_ttl: String,
+}
+
+impl Default for WebhookAuthentication {
+    fn default() -> Self {
+        WebhookAuthentication { enabled: true, cache_ttl: "2m0s".to_string() }
+    }
+}
+
+/// Whether requests without credentials are served as `system:anonymous`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnonymousAuthentication {
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KubeletAuthentication {
+    pub x509: X509Authentication,
+    pub webhook: WebhookAuthentication,
+    pub anonymous: AnonymousAuthentication,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthorizationMode {
+    AlwaysAllow,
+    Webhook,
+}
+
+/// `SubjectAccessReview` cache settings, as Go durations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookAuthorization {
+    #[serde(rename = "cacheAuthorizedTTL")]
+    pub cache_authorized_ttl: String,
+    #[serde(rename = "cacheUnauthorizedTTL")]
+    pub cache_unauthorized_ttl: String,
+}
+
+impl Default for WebhookAuthorization {
+    fn default() -> Self {
+        WebhookAuthorization {
+            cache_authorized_ttl: "5m0s".to_string(),
+            cache_unauthorized_ttl: "30s".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KubeletAuthorization {
+    pub mode: AuthorizationMode,
+    pub webhook: WebhookAuthorization,
+}
+
+impl Default for KubeletAuthorization {
+    fn default() -> Self {
+        KubeletAuthorization { mode: AuthorizationMode::Webhook, webhook: WebhookAuthorization::default() }
+    }
+}
+
+/// Kubelet settings, read from a KubeletConfiguration style YAML file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KubeletConfig {
+    pub root_dir: String,
+    #[serde(rename = "nodeIP")]
+    pub node_ip: Option<String>,
+    pub cgroup_driver: CgroupDriver,
+    pub allowed_unsafe_sysctls: Vec<String>,
+    pub seccomp_default: bool,
+    pub user_namespaces: UserNamespacePool,
+    #[serde(rename = "clusterDNS")]
+    pub cluster_dns: Vec<String>,
+    pub cluster_domain: String,
+    pub resolv_conf: String,
+    /// Size a container log may reach before it is rotated, e.g. `10Mi`.
+    pub container_log_max_size: String,
+    /// Log files kept per container, including the one being written.
+    pub container_log_max_files: usize,
+    /// Whether rotated container logs, except the newest, are gzipped.
+    pub container_log_compress: bool,
+    /// Address the kubelet API listens on.
+    pub address: String,
+    pub port: u16,
+    pub tls_cert_file: String,
+    pub tls_private_key_file: String,
+    pub authentication: KubeletAuthentication,
+    pub authorization: KubeletAuthorization,
+    /// Kubeconfig used to request a client certificate when `kubeconfig`
+    /// has none yet; bootstrapping is off when empty.
+    pub bootstrap_kubeconfig: String,
+    /// Kubeconfig written by TLS bootstrapping and used for API access.
+    pub kubeconfig: String,
+    /// Directory issued certificates and their keys are written to.
+    pub cert_dir: String,
+    /// Renew the bootstrapped client certificate before it expires.
+    pub rotate_certificates: bool,
+    /// Request the serving certificate through a CSR and keep it rotated,
+    /// instead of serving `tlsCertFile`.
+    #[serde(rename = "serverTLSBootstrap")]
+    pub server_tls_bootstrap: bool,
+    pub max_pods: u32,
+    /// Resources held back for Kubernetes daemons, e.g. `cpu: 100m`.
+    pub kube_reserved: BTreeMap<String, String>,
+    /// Resources held back for OS daemons.
+    pub system_reserved: BTreeMap<String, String>,
+    /// Hard eviction thresholds, quantities or percentages of capacity.
+    pub eviction_hard: BTreeMap<String, String>,
+    /// Redirect exec, attach and port-forward clients to the runtime's
+    /// streaming server instead of proxying the stream.
+    pub redirect_container_streaming: bool,
+}
+
+impl Default for KubeletConfig {
+    fn default() -> Self {
+        KubeletConfig {
+            root_dir: "/var/lib/kubelet".to_string(),
+            node_ip: None,
+            cgroup_driver: CgroupDriver::Cgroupfs,
+            allowed_unsafe_sysctls: vec![],
+            seccomp_default: false,
+            user_namespaces: UserNamespacePool {
+                first_id: 65536,
+                length: 65536 * 1024,
+            },
+            cluster_dns: vec![],
+            cluster_domain: "cluster.local".to_string(),
+            resolv_conf: "/etc/resolv.conf".to_string(),
+            container_log_max_size: "10Mi".to_string(),
+            container_log_max_files: 5,
+            container_log_compress: true,
+            address: "0.0.0.0".to_string(),
+            port: 10250,
+            tls_cert_file: "mycert.crt".to_string(),
+            tls_private_key_file: "mycert.key".to_string(),
+            authentication: KubeletAuthentication::default(),
+            authorization: KubeletAuthorization::default(),
+            bootstrap_kubeconfig: String::new(),
+            kubeconfig: "/var/lib/kubelet/kubeconfig".to_string(),
+            cert_dir: "/var/lib/kubelet/pki".to_string(),
+            rotate_certificates: true,
+            server_tls_bootstrap: false,
+            max_pods: 110,
+            kube_reserved: BTreeMap::new(),
+            system_reserved: BTreeMap::new(),
+            eviction_hard: BTreeMap::from([
+                ("memory.available".to_string(), "100Mi".to_string()),
+                ("nodefs.available".to_string(), "10%".to_string()),
+                ("nodefs.inodesFree".to_string(), "5%".to_string()),
+                ("imagefs.available".to_string(), "15%".to_string()),
+            ]),
+            redirect_container_streaming: false,
+        }
+    }
+}
+
+impl KubeletConfig {
+    /// Loads the file named by `KUBELET_CONFIG`, falling back to
+    /// `/var/lib/kubelet/config.yaml` and then to the defaults.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = std::env::var(CONFIG_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        if !Path::new(&path).exists() {
+            info!("no kubelet config at {}, using defaults", path);
+            return Ok(KubeletConfig::default());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        serde_yaml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Unable to parse kubelet config {}: {}", path, e))
+    }
+
+    /// Per-pod state directory, `<rootDir>/pods/<uid>`.
+    pub fn pod_dir(&self, pod_uid: &str) -> PathBuf {
+        Path::new(&self.root_dir).join("pods").join(pod_uid)
+    }
+}
+
+pub fn init() -> anyhow::Result<()> {
+    let config = KubeletConfig::load()?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn config() -> &'static KubeletConfig {
+    CONFIG.get_or_init(KubeletConfig::default)
+}
+
+/// Parses a Go style duration such as `2m0s`, `1h30m` or `500ms`.
+pub fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    if value == "0" {
+        return Ok(Duration::ZERO);
+    }
+    let invalid = || anyhow::anyhow!("invalid duration {:?}", value);
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    let mut rest = value;
+    let mut total = 0f64;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(invalid)?;
+        let number: f64 = rest[..number_len].parse().map_err(|_| invalid())?;
+        rest = &rest[number_len..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len());
+        let seconds = match &rest[..unit_len] {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        total += number * seconds;
+        rest = &rest[unit_len..];
+    }
+    Ok(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("0").unwrap(), Duration::ZERO);
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("300ms").unwrap().as_millis(), 300);
+        assert_eq!(parse_duration("250us").unwrap().as_micros(), 250);
+    }
+
+    #[test]
+    fn parses_compound_durations() {
+        assert_eq!(parse_duration("2m0s").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("1h1m1s").unwrap(), Duration::from_secs(3661));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for value in ["", "5", "s", "1d", "-1s", "1.2.3s", "1 s", "m5"] {
+            assert!(parse_duration(value).is_err(), "{:?} should not parse", value);
+        }
+    }
+}
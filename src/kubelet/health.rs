@@ -0,0 +1,41 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The sync loop is unhealthy once it has not iterated for this long.
+const SYNC_LOOP_THRESHOLD: Duration = Duration::from_secs(5 * 60);
+/// PLEG is unhealthy once it has not relisted for this long.
+const RELIST_THRESHOLD: Duration = Duration::from_secs(3 * 60);
+
+static LAST_SYNC_LOOP: Mutex<Option<Instant>> = Mutex::new(None);
+static LAST_RELIST: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Marks an iteration of the pod watch loop.
+pub fn sync_loop_tick() {
+    *LAST_SYNC_LOOP.lock().unwrap() = Some(Instant::now());
+}
+
+/// Marks a successful relist of the runtime's pods and containers.
+pub fn relisted() {
+    *LAST_RELIST.lock().unwrap() = Some(Instant::now());
+}
+
+fn check(last: &Mutex<Option<Instant>>, threshold: Duration, what: &str) -> Result<(), String> {
+    match *last.lock().unwrap() {
+        None => Err(format!("{} has yet to be successful", what)),
+        Some(at) if at.elapsed() > threshold => Err(format!(
+            "{} was last seen active {:?} ago; threshold is {:?}",
+            what,
+            at.elapsed(),
+            threshold
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+pub fn sync_loop_healthy() -> Result<(), String> {
+    check(&LAST_SYNC_LOOP, SYNC_LOOP_THRESHOLD, "sync loop")
+}
+
+pub fn pleg_healthy() -> Result<(), String> {
+    check(&LAST_RELIST, RELIST_THRESHOLD, "pleg")
+}
@@ -8,30 +8,37 @@ use kube::error::ErrorResponse;
 use kube::Error;
 use tracing::{debug, error, info};
 
+use crate::kubelet::client;
+use crate::kubelet::config::config;
+use crate::metrics;
 use crate::nodemod;
+use crate::nodemod::capacity;
 
-pub struct Kubelet {
-    kube_config: kube::Config,
-}
+/// Name this node registers with.
+pub const NODE_NAME: &str = "my-imac";
+
+/// Registers the node and keeps its lease and status fresh. Clients are
+/// fetched per use so a rotated client certificate is picked up.
+pub struct Kubelet;
 
 impl Kubelet {
-    pub async fn new(kube_config: kube::Config) -> Self {
-        Kubelet { kube_config }
+    pub async fn new() -> Self {
+        Kubelet
     }
 
     pub async fn start(&self) {
-        let client = kube::Client::try_from(self.kube_config.clone()).unwrap();
+        let client = client::client().await.unwrap();
         let node_client: Api<KubeNode> = Api::all(client.clone());
-        match node_client.get("my-imac").await {
+        match node_client.get(NODE_NAME).await {
             Ok(_) => {
                 info!("节点已经存在,更新租约");
-                let uid = self.uid(&client.clone(), "my-imac").await;
-                self.update(uid.as_str(), "my-imac").await;
+                let uid = self.uid(&client.clone(), NODE_NAME).await;
+                self.update(uid.as_str(), NODE_NAME).await;
             }
             Err(Error::Api(ErrorResponse { code: 404, .. })) => {
                 self.create().await;
-                let uid = self.uid(&client.clone(), "my-imac").await;
-                self.update(uid.as_str(), "my-imac").await;
+                let uid = self.uid(&client.clone(), NODE_NAME).await;
+                self.update(uid.as_str(), NODE_NAME).await;
             }
             Err(e) => {
                 error!(
@@ -43,8 +50,11 @@ impl Kubelet {
     }
 
     async fn update_status(&self, node_name: &str, client: &kube::Client) -> anyhow::Result<()> {
+        let (capacity, allocatable) = capacity::node_resources();
         let status_patch = serde_json::json!({
             "status": {
+                "capacity": capacity,
+                "allocatable": allocatable,
                 "conditions": [
                     {
                         "lastHeartbeatTime": Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
@@ -70,25 +80,32 @@ impl Kubelet {
     }
 
     async fn create(&self) {
-        let client = kube::Client::try_from(self.kube_config.clone()).unwrap();
+        let client = client::client().await.unwrap();
         let node_client: Api<KubeNode> = Api::all(client.clone());
         let mut builder = nodemod::node::Node::builder();
-        builder.set_name("my-imac");
+        builder.set_name(NODE_NAME);
         builder.add_annotation("node.alpha.kubernetes.io/ttl", "0");
         builder.add_annotation(
             "volumes.kubernetes.io/controller-managed-attach-detach",
             "true",
         );
-        builder.add_label("kubernetes.io/hostname", "my-imac");
+        builder.add_label("kubernetes.io/hostname", NODE_NAME);
         builder.add_label("node-role.kubernetes.io/worker", "");
-        builder.add_capacity("cpu", "4");
+        let (capacity, allocatable) = capacity::node_resources();
+        for (name, quantity) in &capacity {
+            builder.add_capacity(name, &quantity.0);
+        }
+        for (name, quantity) in &allocatable {
+            builder.add_allocatable(name, &quantity.0);
+        }
+        builder.set_port(config().port as i32);
 
         let node = builder.build().into_inner();
 
         match node_client.create(&PostParams::default(), &node).await {
             Ok(node) => {
                 let node_uid = node.metadata.uid.unwrap();
-                create_lease(&node_uid, "my-imac", &client).await;
+                create_lease(&node_uid, NODE_NAME, &client).await;
                 info!("Successfully created node");
             }
             Err(e) => {
@@ -96,7 +113,6 @@ impl Kubelet {
                     error = %e,
                     "Exhausted retries creating node after failed create. Not retrying"
                 );
-                return;
             }
         }
     }
@@ -108,12 +124,12 @@ impl Kubelet {
     }
 
     async fn update(&self, node_uid: &str, node_name: &str) {
-        let client = kube::Client::try_from(self.kube_config.clone()).unwrap();
         loop {
-            self.update_lease(&node_uid, node_name)
+            self.update_lease(node_uid, node_name)
                 .await
                 .expect("TODO: panic message");
-            self.update_status(node_name, &client.clone())
+            let client = client::client().await.unwrap();
+            self.update_status(node_name, &client)
                 .await
                 .expect("TODO: panic message");
             thread::sleep(time::Duration::from_secs(20));
@@ -121,9 +137,10 @@ impl Kubelet {
     }
 
     async fn update_lease(&self, node_uid: &str, node_name: &str) -> Result<Lease, Error> {
-        let client = kube::Client::try_from(self.kube_config.clone()).unwrap();
+        let client = client::client().await.unwrap();
         let leases: Api<Lease> = Api::namespaced(client.clone(), "kube-node-lease");
         let lease = lease_definition(node_uid, node_name);
+        let start = time::Instant::now();
         let resp = leases
             .patch(
                 node_name,
@@ -131,6 +148,7 @@ impl Kubelet {
                 &kube::api::Patch::Strategic(lease),
             )
             .await;
+        metrics::kubelet::record_lease_renewal(start.elapsed());
         match &resp {
             Ok(_) => debug!("租约更新成功"),
             Err(e) => error!("更新租约失败 {e}"),
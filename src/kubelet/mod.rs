@@ -1,2 +1,7 @@
+pub mod bootstrap;
+pub mod certificate;
+pub mod client;
+pub mod config;
+pub mod health;
 pub mod minikubelet;
 pub mod operator;
\ No newline at end of file
@@ -0,0 +1,69 @@
+pub mod reader;
+pub mod rotation;
+
+use std::path::{Path, PathBuf};
+
+use tracing::*;
+
+pub const POD_LOGS_ROOT: &str = "/var/log/pods";
+pub const CONTAINER_LOGS_ROOT: &str = "/var/log/containers";
+
+/// File names are limited to 255 bytes; leave room for the `.log` suffix.
+const MAX_SYMLINK_NAME_LEN: usize = 251;
+
+/// `/var/log/pods/<namespace>_<name>_<uid>`, the sandbox log directory.
+pub fn pod_log_dir(namespace: &str, name: &str, uid: &str) -> PathBuf {
+    Path::new(POD_LOGS_ROOT).join(format!("{}_{}_{}", namespace, name, uid))
+}
+
+/// Container log path relative to the pod log directory.
+pub fn container_log_path(container_name: &str, restart_count: u32) -> PathBuf {
+    Path::new(container_name).join(format!("{}.log", restart_count))
+}
+
+/// `/var/log/containers/<pod>_<namespace>_<container>-<containerID>.log`,
+/// the flat layout log shippers expect.
+pub fn container_log_symlink(pod_name: &str, namespace: &str, container_name: &str, container_id: &str) -> PathBuf {
+    let mut name = format!("{}_{}_{}-{}", pod_name, namespace, container_name, container_id);
+    if name.len() > MAX_SYMLINK_NAME_LEN {
+        name.truncate(MAX_SYMLINK_NAME_LEN);
+    }
+    Path::new(CONTAINER_LOGS_ROOT).join(format!("{}.log", name))
+}
+
+/// Creates the directory a container of the pod writes its logs into.
+pub async fn create_container_log_dir(pod_log_dir: &Path, container_name: &str) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(pod_log_dir.join(container_name)).await
+}
+
+pub async fn create_log_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(CONTAINER_LOGS_ROOT).await?;
+    let _ = tokio::fs::remove_file(link).await;
+    tokio::fs::symlink(target, link).await
+}
+
+/// Removes a pod's log directory and the symlinks that pointed into it.
+pub async fn remove_pod_logs(namespace: &str, name: &str, uid: &str) {
+    let dir = pod_log_dir(namespace, name, uid);
+    if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Unable to remove pod log directory {}: {}", dir.display(), e);
+        }
+    }
+    let Ok(mut entries) = tokio::fs::read_dir(CONTAINER_LOGS_ROOT).await else {
+        return;
+    };
+    let prefix = format!("{}_{}_", name, namespace);
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        let points_into_pod = tokio::fs::read_link(entry.path())
+            .await
+            .map(|target| target.starts_with(&dir))
+            .unwrap_or(false);
+        if points_into_pod {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::VecDeque;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time;
+use tracing::*;
+
+use crate::logs::rotation;
+use crate::provider::{cri, get_client};
+
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of a container log, with partial lines already joined.
+#[derive(Clone, Debug)]
+pub struct LogMessage {
+    pub timestamp: DateTime<Utc>,
+    pub stream: Stream,
+    pub log: Vec<u8>,
+}
+
+/// What to return from a container log, mirroring the `PodLogOptions` query
+/// parameters.
+#[derive(Clone, Debug)]
+pub struct LogOptions {
+    pub follow: bool,
+    pub tail_lines: Option<usize>,
+    pub since_seconds: Option<i64>,
+    pub since_time: Option<DateTime<Utc>>,
+    pub limit_bytes: Option<usize>,
+    pub timestamps: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions {
+            follow: false,
+            tail_lines: None,
+            since_seconds: None,
+            since_time: None,
+            limit_bytes: None,
+            timestamps: false,
+            stdout: true,
+            stderr: true,
+        }
+    }
+}
+
+impl LogOptions {
+    fn since(&self) -> Option<DateTime<Utc>> {
+        self.since_time
+            .or_else(|| self.since_seconds.map(|s| Utc::now() - chrono::Duration::seconds(s)))
+    }
+}
+
+/// Splits a line in the CRI log format,
+/// `<RFC3339Nano timestamp> <stdout|stderr> <P|F> <message>`, returning the
+/// timestamp, stream, whether the line is partial and the message.
+pub fn parse_cri_log(line: &[u8]) -> anyhow::Result<(DateTime<Utc>, Stream, bool, &[u8])> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let mut fields = line.splitn(4, |b| *b == b' ');
+    let (Some(timestamp), Some(stream), Some(tag)) = (fields.next(), fields.next(), fields.next()) else {
+        anyhow::bail!("invalid CRI log line {:?}", String::from_utf8_lossy(line));
+    };
+    let timestamp = DateTime::parse_from_rfc3339(std::str::from_utf8(timestamp)?)
+        .map_err(|e| anyhow::anyhow!("invalid CRI log timestamp: {}", e))?
+        .with_timezone(&Utc);
+    let stream = match stream {
+        b"stdout" => Stream::Stdout,
+        b"stderr" => Stream::Stderr,
+        other => anyhow::bail!("unexpected stream type {:?}", String::from_utf8_lossy(other)),
+    };
+    // Tags may carry more fields after a ':' separator; only the first matters.
+    let partial = tag.split(|b| *b == b':').next() == Some(b"P".as_slice());
+    Ok((timestamp, stream, partial, fields.next().unwrap_or_default()))
+}
+
+/// Joins partial lines per stream into complete messages.
+#[derive(Default)]
+struct Parser {
+    stdout: Option<LogMessage>,
+    stderr: Option<LogMessage>,
+}
+
+impl Parser {
+    /// Malformed lines are skipped rather than ending the stream.
+    fn feed(&mut self, line: &[u8]) -> Option<LogMessage> {
+        self.parse(line).unwrap_or_else(|e| {
+            warn!("Failed to parse container log line: {}", e);
+            None
+        })
+    }
+
+    fn parse(&mut self, line: &[u8]) -> anyhow::Result<Option<LogMessage>> {
+        let (timestamp, stream, partial, content) = parse_cri_log(line)?;
+        let pending = match stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        let message = pending.get_or_insert_with(|| LogMessage { timestamp, stream, log: vec![] });
+        message.log.extend_from_slice(content);
+        if partial {
+            return Ok(None);
+        }
+        message.log.push(b'\n');
+        Ok(pending.take())
+    }
+}
+
+/// Applies stream selection, `since`, `timestamps` and `limitBytes` while
+/// writing messages out.
+struct LogWriter<'a, W> {
+    out: &'a mut W,
+    options: &'a LogOptions,
+    since: Option<DateTime<Utc>>,
+    remaining: Option<usize>,
+}
+
+impl<W: AsyncWrite + Unpin> LogWriter<'_, W> {
+    fn wants(&self, message: &LogMessage) -> bool {
+        let stream = match message.stream {
+            Stream::Stdout => self.options.stdout,
+            Stream::Stderr => self.options.stderr,
+        };
+        stream && self.since.is_none_or(|since| message.timestamp >= since)
+    }
+
+    /// Returns false once `limitBytes` has been reached.
+    async fn write(&mut self, message: &LogMessage) -> anyhow::Result<bool> {
+        let mut buf = vec![];
+        if self.options.timestamps {
+            buf.extend_from_slice(message.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true).as_bytes());
+            buf.push(b' ');
+        }
+        buf.extend_from_slice(&message.log);
+        if let Some(remaining) = self.remaining.as_mut() {
+            buf.truncate(*remaining);
+            *remaining -= buf.len();
+        }
+        self.out.write_all(&buf).await?;
+        self.out.flush().await?;
+        Ok(self.remaining != Some(0))
+    }
+}
+
+type LogReader = Box<dyn AsyncBufRead + Unpin + Send>;
+
+async fn open_log(path: &Path) -> std::io::Result<LogReader> {
+    if !path.to_string_lossy().ends_with(".gz") {
+        return Ok(Box::new(BufReader::new(tokio::fs::File::open(path).await?)));
+    }
+    let path = path.to_path_buf();
+    let content = tokio::task::spawn_blocking(move || {
+        let mut decoded = vec![];
+        std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(std::fs::File::open(path)?), &mut decoded)?;
+        Ok::<_, std::io::Error>(decoded)
+    })
+    .await??;
+    Ok(Box::new(std::io::Cursor::new(content)))
+}
+
+/// Writes a followed line; false once `limitBytes` has been reached.
+async fn follow_line<W: AsyncWrite + Unpin>(
+    parser: &mut Parser,
+    writer: &mut LogWriter<'_, W>,
+    line: &[u8],
+) -> anyhow::Result<bool> {
+    match parser.feed(line).filter(|m| writer.wants(m)) {
+        Some(message) => writer.write(&message).await,
+        None => Ok(true),
+    }
+}
+
+async fn container_running(container_id: &str) -> bool {
+    let request = cri::ContainerStatusRequest { container_id: container_id.to_string(), verbose: false };
+    match get_client().await.container_status(request).await {
+        Ok(response) => response
+            .into_inner()
+            .status
+            .is_some_and(|s| s.state == cri::ContainerState::ContainerRunning as i32),
+        Err(_) => false,
+    }
+}
+
+fn inode(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|m| m.ino())
+}
+
+/// Streams a container's logs, oldest rotated file first, into `out`.
+/// With `follow`, keeps reading the live file across rotations until the
+/// container stops.
+pub async fn read_logs<W: AsyncWrite + Unpin>(
+    log_path: &Path,
+    container_id: &str,
+    options: &LogOptions,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    let mut files: Vec<PathBuf> = rotation::rotated_logs(log_path)
+        .await?
+        .into_iter()
+        .filter(|p| !p.to_string_lossy().ends_with(".tmp"))
+        .collect();
+    files.push(log_path.to_path_buf());
+
+    let mut writer = LogWriter { out, options, since: options.since(), remaining: options.limit_bytes };
+    if writer.remaining == Some(0) {
+        return Ok(());
+    }
+    let mut parser = Parser::default();
+    let mut tail: VecDeque<LogMessage> = VecDeque::new();
+    let mut live: Option<(LogReader, Option<u64>)> = None;
+    let mut line = vec![];
+
+    for path in &files {
+        let mut reader = match open_log(path).await {
+            Ok(reader) => reader,
+            // A rotated file may be compressed or removed while we list them.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let inode = inode(path);
+        line.clear();
+        while reader.read_until(b'\n', &mut line).await? > 0 {
+            if !line.ends_with(b"\n") && path == log_path {
+                // The runtime is mid-write; the rest is picked up when following.
+                break;
+            }
+            if let Some(message) = parser.feed(&line).filter(|m| writer.wants(m)) {
+                match options.tail_lines {
+                    Some(0) => {}
+                    Some(n) => {
+                        if tail.len() == n {
+                            tail.pop_front();
+                        }
+                        tail.push_back(message);
+                    }
+                    None => {
+                        if !writer.write(&message).await? {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            line.clear();
+        }
+        if path == log_path {
+            live = Some((reader, inode));
+        }
+    }
+    for message in tail.drain(..) {
+        if !writer.write(&message).await? {
+            return Ok(());
+        }
+    }
+    if !options.follow {
+        return Ok(());
+    }
+
+    let Some((mut reader, mut current_inode)) = live else {
+        return Ok(());
+    };
+    loop {
+        let read = reader.read_until(b'\n', &mut line).await?;
+        if read > 0 && line.ends_with(b"\n") {
+            if !follow_line(&mut parser, &mut writer, &line).await? {
+                return Ok(());
+            }
+            line.clear();
+            continue;
+        }
+        // Caught up with the writer: switch files after a rotation, or stop
+        // once the container is no longer running.
+        let latest = inode(log_path);
+        if latest.is_some() && latest != current_inode {
+            // The runtime keeps writing to the rotated file until it reopens
+            // its log, so finish that file first.
+            while reader.read_until(b'\n', &mut line).await? > 0 {
+                if line.ends_with(b"\n") {
+                    if !follow_line(&mut parser, &mut writer, &line).await? {
+                        return Ok(());
+                    }
+                    line.clear();
+                }
+            }
+            // An unterminated last line must not be glued onto the new file's first.
+            line.clear();
+            reader = open_log(log_path).await?;
+            current_inode = latest;
+            continue;
+        }
+        if !container_running(container_id).await {
+            return Ok(());
+        }
+        time::sleep(FOLLOW_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cri_line() {
+        let (timestamp, stream, partial, content) =
+            parse_cri_log(b"2016-10-06T00:17:09.669794202Z stderr F hello world\n").unwrap();
+        assert_eq!(timestamp.timestamp_subsec_nanos(), 669794202);
+        assert_eq!(stream, Stream::Stderr);
+        assert!(!partial);
+        assert_eq!(content, b"hello world");
+    }
+
+    #[test]
+    fn joins_partial_lines_per_stream() {
+        let mut parser = Parser::default();
+        assert!(parser.feed(b"2016-10-06T00:17:09.669794202Z stdout P hello \n").is_none());
+        let stderr = parser.feed(b"2016-10-06T00:17:09.669794203Z stderr F oops\n").unwrap();
+        assert_eq!(stderr.log, b"oops\n");
+        let stdout = parser.feed(b"2016-10-06T00:17:10.000000000Z stdout F world\n").unwrap();
+        assert_eq!(stdout.stream, Stream::Stdout);
+        assert_eq!(stdout.log, b"hello world\n");
+        assert_eq!(stdout.timestamp.timestamp_subsec_nanos(), 669794202);
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        assert!(parse_cri_log(b"yesterday stdout F hello\n").is_err());
+        assert!(parse_cri_log(b"2016-10-06T00:17:09Z stdin F hello\n").is_err());
+        assert!(parse_cri_log(b"2016-10-06T00:17:09Z\n").is_err());
+        let mut parser = Parser::default();
+        assert!(parser.feed(b"yesterday stdout F hello\n").is_none());
+        assert_eq!(parser.feed(b"2016-10-06T00:17:09Z stdout F hello\n").unwrap().log, b"hello\n");
+    }
+
+    #[tokio::test]
+    async fn selects_streams() {
+        let path = std::env::temp_dir().join(format!("rust-kubelet-reader-{}.log", std::process::id()));
+        tokio::fs::write(
+            &path,
+            "2016-10-06T00:17:09Z stdout F out\n2016-10-06T00:17:10Z stderr F err\n2016-10-06T00:17:11Z stdout F again\n",
+        )
+        .await
+        .unwrap();
+        let options = LogOptions { stderr: false, ..Default::default() };
+        let mut out = vec![];
+        let result = read_logs(&path, "", &options, &mut out).await;
+        let _ = tokio::fs::remove_file(&path).await;
+        result.unwrap();
+        assert_eq!(out, b"out\nagain\n");
+    }
+}
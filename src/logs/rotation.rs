@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::time;
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::provider::{cri, get_client, quantity};
+
+const MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const COMPRESS_SUFFIX: &str = ".gz";
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Periodically rotates the logs of running containers that grew past
+/// `containerLogMaxSize`, keeping at most `containerLogMaxFiles` per container
+/// and compressing older files when `containerLogCompress` is set.
+pub async fn run() {
+    let max_size = match quantity::parse(&config().container_log_max_size) {
+        Ok(q) if q.value() > 0 => q.value() as u64,
+        _ => {
+            error!("invalid containerLogMaxSize {:?}, log rotation disabled", config().container_log_max_size);
+            return;
+        }
+    };
+    let max_files = config().container_log_max_files;
+    if max_files < 2 {
+        error!("containerLogMaxFiles must be at least 2, log rotation disabled");
+        return;
+    }
+    let compress = config().container_log_compress;
+    loop {
+        if let Err(e) = rotate_logs(max_size, max_files, compress).await {
+            warn!("容器日志轮转失败: {}", e);
+        }
+        time::sleep(MONITOR_INTERVAL).await;
+    }
+}
+
+async fn rotate_logs(max_size: u64, max_files: usize, compress: bool) -> anyhow::Result<()> {
+    let filter = cri::ContainerFilter {
+        state: Some(cri::ContainerStateValue { state: cri::ContainerState::ContainerRunning as i32 }),
+        ..Default::default()
+    };
+    let containers = get_client().await
+        .list_containers(cri::ListContainersRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .containers;
+    for container in containers {
+        let request = cri::ContainerStatusRequest { container_id: container.id.clone(), verbose: false };
+        let log_path = match get_client().await.container_status(request).await {
+            Ok(response) => response.into_inner().status.map(|s| s.log_path).unwrap_or_default(),
+            Err(e) => {
+                warn!("获取容器状态失败 {}: {}", container.id, e);
+                continue;
+            }
+        };
+        if log_path.is_empty() {
+            continue;
+        }
+        if let Err(e) = rotate_log(&container.id, Path::new(&log_path), max_size, max_files, compress).await {
+            warn!("Unable to rotate log {} of container {}: {}", log_path, container.id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn rotate_log(
+    container_id: &str,
+    log: &Path,
+    max_size: u64,
+    max_files: usize,
+    compress: bool,
+) -> anyhow::Result<()> {
+    let size = match tokio::fs::metadata(log).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if size < max_size {
+        return Ok(());
+    }
+    let rotated = cleanup_unused_logs(log).await?;
+    let rotated = remove_excess_logs(rotated, max_files).await?;
+    if compress {
+        compress_logs(&rotated).await?;
+    }
+    rotate_latest_log(container_id, log).await
+}
+
+/// Rotated files of a log, oldest first. The timestamp suffix sorts in
+/// chronological order.
+pub async fn rotated_logs(log: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (log.parent(), log.file_name()) else {
+        return Ok(vec![]);
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut rotated = vec![];
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            rotated.push(entry.path());
+        }
+    }
+    rotated.sort();
+    Ok(rotated)
+}
+
+/// Removes temporary files left behind by an interrupted compression.
+async fn cleanup_unused_logs(log: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut rotated = vec![];
+    for path in rotated_logs(log).await? {
+        if path.to_string_lossy().ends_with(TMP_SUFFIX) {
+            tokio::fs::remove_file(&path).await?;
+        } else {
+            rotated.push(path);
+        }
+    }
+    Ok(rotated)
+}
+
+/// Leaves room for the current log and the one about to be rotated.
+async fn remove_excess_logs(mut rotated: Vec<PathBuf>, max_files: usize) -> std::io::Result<Vec<PathBuf>> {
+    let max_rotated = max_files - 2;
+    if rotated.len() <= max_rotated {
+        return Ok(rotated);
+    }
+    for path in rotated.drain(..rotated.len() - max_rotated) {
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(rotated)
+}
+
+/// Compresses every rotated log except the newest, which the runtime may
+/// still be flushing to.
+async fn compress_logs(rotated: &[PathBuf]) -> anyhow::Result<()> {
+    let Some((_, older)) = rotated.split_last() else {
+        return Ok(());
+    };
+    for path in older {
+        if path.to_string_lossy().ends_with(COMPRESS_SUFFIX) {
+            continue;
+        }
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || compress_log(&path)).await??;
+    }
+    Ok(())
+}
+
+fn compress_log(path: &Path) -> std::io::Result<()> {
+    let compressed = PathBuf::from(format!("{}{}", path.display(), COMPRESS_SUFFIX));
+    let tmp = PathBuf::from(format!("{}{}", compressed.display(), TMP_SUFFIX));
+    let mut input = std::fs::File::open(path)?;
+    let mut encoder = flate2::write::GzEncoder::new(std::fs::File::create(&tmp)?, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::rename(&tmp, &compressed)?;
+    std::fs::remove_file(path)
+}
+
+/// Renames the current log with a timestamp suffix and asks the runtime to
+/// reopen it, so that new output goes to a fresh file.
+async fn rotate_latest_log(container_id: &str, log: &Path) -> anyhow::Result<()> {
+    let timestamp = chrono::Local::now().format(TIMESTAMP_FORMAT);
+    let rotated = PathBuf::from(format!("{}.{}", log.display(), timestamp));
+    tokio::fs::rename(log, &rotated).await?;
+    let request = cri::ReopenContainerLogRequest { container_id: container_id.to_string() };
+    if let Err(e) = get_client().await.reopen_container_log(request).await {
+        // Put the log back so the runtime keeps writing to a file we track.
+        tokio::fs::rename(&rotated, log).await?;
+        anyhow::bail!("ReopenContainerLog failed: {}", e);
+    }
+    info!("轮转容器日志 {} -> {}", log.display(), rotated.display());
+    Ok(())
+}
@@ -1,17 +1,19 @@
+use std::time::Duration;
+
 use futures::{StreamExt, TryStreamExt};
 use k8s_openapi::api::core::v1::Pod;
-use kube::{
-    api::{Api, ListParams, ResourceExt, WatchEvent},
-    Client, Config,
-};
-use tokio;
+use kube::api::{Api, ListParams, ResourceExt, WatchEvent};
 use tracing::*;
 
-use provider::pod;
+use provider::{pod, service};
 
 mod kubelet;
+mod logs;
+mod metrics;
 mod nodemod;
 mod provider;
+mod server;
+mod stats;
 
 #[tokio::main]
 async fn main() {
@@ -23,36 +25,76 @@ async fn main() {
         )
         .init();
     info!("Preparing kubelet config.");
-    let local_config = Config::infer()
+    kubelet::config::init().expect("Unable to load kubelet config");
+    provider::admission::init().expect("Invalid admission settings");
+    provider::userns::init().expect("Unable to restore user namespace allocations");
+    let local_config = kubelet::bootstrap::kube_config()
         .await
         .map_err(|e| anyhow::anyhow!("Unable to load config from host: {}", e))
         .expect("TODO: panic message");
+    kubelet::client::init(local_config.clone());
 
-    let kubelet_ins = kubelet::minikubelet::Kubelet::new(local_config).await;
+    let server_config = local_config;
+    let kubelet_ins = kubelet::minikubelet::Kubelet::new().await;
 
+    tokio::spawn(service::watch_services());
     tokio::spawn(my_watch());
+    tokio::spawn(logs::rotation::run());
+    tokio::spawn(stats::sample_cpu());
+    tokio::spawn(pod::fetch_status_info());
+    tokio::spawn(kubelet::bootstrap::rotate_client_certificate());
+    tokio::spawn(async move {
+        if let Err(e) = server::serve(server_config).await {
+            error!("kubelet API server failed: {}", e);
+        }
+    });
     kubelet_ins.start().await;
 }
 
 async fn my_watch() -> anyhow::Result<()> {
-    let client = Client::try_default().await.unwrap();
-    let pods: Api<Pod> = Api::namespaced(client, "default");
     let lp = ListParams::default();
-    let mut stream = pods.watch(&lp, "0").await?.boxed();
+    let mut rotated = kubelet::client::rotated();
+    // Ticks keep the loop iterating, and so /healthz passing, while no pod changes.
+    let mut housekeeping = tokio::time::interval(Duration::from_secs(2));
+    let mut resource_version = "0".to_string();
 
-    while let Some(status) = stream.try_next().await? {
-        match status {
-            WatchEvent::Added(o) => {
-                tokio::spawn(pod::run_pod(o));
-            }
-            WatchEvent::Modified(o) => {
-                info!("update {}", o.name_any());
+    loop {
+        // A new client per watch, so a rotated certificate is used once the old watch is dropped.
+        let pods: Api<Pod> = Api::namespaced(kubelet::client::client().await?, "default");
+        let mut stream = pods.watch(&lp, &resource_version).await?.boxed();
+        loop {
+            kubelet::health::sync_loop_tick();
+            let status = tokio::select! {
+                status = stream.try_next() => match status? {
+                    Some(status) => status,
+                    None => return Ok(()),
+                },
+                _ = housekeeping.tick() => continue,
+                _ = rotated.changed() => {
+                    info!("客户端证书已轮换, 重新建立pod watch");
+                    break;
+                }
+            };
+            match status {
+                WatchEvent::Added(o) => {
+                    resource_version = o.resource_version().unwrap_or(resource_version);
+                    tokio::spawn(pod::run_pod(o));
+                }
+                WatchEvent::Modified(o) => {
+                    resource_version = o.resource_version().unwrap_or(resource_version);
+                    info!("update {}", o.name_any());
+                    if o.metadata.deletion_timestamp.is_some() {
+                        tokio::spawn(pod::delete_pod(o));
+                    }
+                }
+                WatchEvent::Deleted(o) => {
+                    resource_version = o.resource_version().unwrap_or(resource_version);
+                    info!("delete {}", o.name_any());
+                    tokio::spawn(pod::delete_pod(o));
+                }
+                WatchEvent::Bookmark(bookmark) => resource_version = bookmark.metadata.resource_version,
+                _ => {}
             }
-            WatchEvent::Deleted(o) => {
-                info!("delete {}", o.name_any());
-            }
-            _ => {}
         }
     }
-    Ok(())
-}
\ No newline at end of file
+}
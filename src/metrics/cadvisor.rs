@@ -0,0 +1,93 @@
+use crate::metrics::{epoch_seconds, seconds, Scrape};
+use crate::provider::qos;
+use crate::stats;
+
+const CONTAINER_LABELS: &[&str] = &["container", "namespace", "pod"];
+const NETWORK_LABELS: &[&str] = &["container", "interface", "namespace", "pod"];
+
+/// `/metrics/cadvisor`: the cAdvisor container metrics dashboards expect,
+/// built from the runtime's stats rather than from cgroups.
+pub async fn cadvisor_metrics() -> anyhow::Result<String> {
+    let scrape = Scrape::new();
+    let machine_cores = scrape.gauge("machine_cpu_cores", "Number of logical CPU cores.", &[])?;
+    let machine_memory = scrape.gauge("machine_memory_bytes", "Amount of memory installed on the machine.", &[])?;
+    let cpu = scrape.counter(
+        "container_cpu_usage_seconds_total",
+        "Cumulative cpu time consumed in seconds.",
+        &["container", "cpu", "namespace", "pod"],
+    )?;
+    let working_set = scrape.gauge("container_memory_working_set_bytes", "Current working set in bytes.", CONTAINER_LABELS)?;
+    let usage = scrape.gauge(
+        "container_memory_usage_bytes",
+        "Current memory usage in bytes, including all memory regardless of when it was accessed",
+        CONTAINER_LABELS,
+    )?;
+    let rss = scrape.gauge("container_memory_rss", "Size of RSS in bytes.", CONTAINER_LABELS)?;
+    let fs_usage = scrape.gauge("container_fs_usage_bytes", "Number of bytes that are consumed by the container on this filesystem.", CONTAINER_LABELS)?;
+    let fs_limit = scrape.gauge("container_fs_limit_bytes", "Number of bytes that can be consumed by the container on this filesystem.", CONTAINER_LABELS)?;
+    let fs_inodes = scrape.gauge("container_fs_inodes_total", "Number of Inodes", CONTAINER_LABELS)?;
+    let start = scrape.gauge("container_start_time_seconds", "Start time of the container since unix epoch in seconds.", CONTAINER_LABELS)?;
+    let rx_bytes = scrape.counter("container_network_receive_bytes_total", "Cumulative count of bytes received", NETWORK_LABELS)?;
+    let rx_errors = scrape.counter("container_network_receive_errors_total", "Cumulative count of errors encountered while receiving", NETWORK_LABELS)?;
+    let tx_bytes = scrape.counter("container_network_transmit_bytes_total", "Cumulative count of bytes transmitted", NETWORK_LABELS)?;
+    let tx_errors = scrape.counter("container_network_transmit_errors_total", "Cumulative count of errors encountered while transmitting", NETWORK_LABELS)?;
+    let processes = scrape.gauge("container_processes", "Number of processes running inside the container.", CONTAINER_LABELS)?;
+
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    machine_cores.with_label_values(&[]).set(cores as f64);
+    machine_memory.with_label_values(&[]).set(qos::machine_memory_capacity() as f64);
+
+    let summary = stats::summary(false).await?;
+    for pod in &summary.pods {
+        let (namespace, name) = (pod.pod_ref.namespace.as_str(), pod.pod_ref.name.as_str());
+        // Pod-level series carry an empty container label, as for the pod cgroup.
+        let pod_labels = ["", namespace, name];
+        for interface in pod.network.iter().flat_map(|n| &n.interfaces) {
+            let labels = ["", interface.name.as_str(), namespace, name];
+            let counters = [
+                (&rx_bytes, interface.rx_bytes),
+                (&rx_errors, interface.rx_errors),
+                (&tx_bytes, interface.tx_bytes),
+                (&tx_errors, interface.tx_errors),
+            ];
+            for (counter, value) in counters {
+                if let Some(value) = value {
+                    counter.with_label_values(&labels).inc_by(value as f64);
+                }
+            }
+        }
+        if let Some(count) = pod.process_stats.as_ref().and_then(|p| p.process_count) {
+            processes.with_label_values(&pod_labels).set(count as f64);
+        }
+        for container in &pod.containers {
+            let labels = [container.name.as_str(), namespace, name];
+            if let Some(value) = container.cpu.as_ref().and_then(|c| c.usage_core_nano_seconds) {
+                cpu.with_label_values(&[container.name.as_str(), "total", namespace, name]).inc_by(seconds(value));
+            }
+            if let Some(memory) = &container.memory {
+                let gauges = [
+                    (&working_set, memory.working_set_bytes),
+                    (&usage, memory.usage_bytes),
+                    (&rss, memory.rss_bytes),
+                ];
+                for (gauge, value) in gauges {
+                    if let Some(value) = value {
+                        gauge.with_label_values(&labels).set(value as f64);
+                    }
+                }
+            }
+            if let Some(rootfs) = &container.rootfs {
+                let gauges = [(&fs_usage, rootfs.used_bytes), (&fs_limit, rootfs.capacity_bytes), (&fs_inodes, rootfs.inodes)];
+                for (gauge, value) in gauges {
+                    if let Some(value) = value {
+                        gauge.with_label_values(&labels).set(value as f64);
+                    }
+                }
+            }
+            if let Some(time) = epoch_seconds(&container.start_time) {
+                start.with_label_values(&labels).set(time);
+            }
+        }
+    }
+    scrape.encode()
+}
@@ -0,0 +1,294 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use http_body::Body;
+use tower::{Layer, Service};
+use tracing::*;
+
+use crate::metrics::kubelet;
+
+#[derive(Clone, Debug)]
+enum Target {
+    Runtime,
+    /// Requests reach the layer before the cluster URL is applied, so the
+    /// host comes from the config.
+    ApiServer { host: String },
+}
+
+/// Records every request passing through a client: CRI calls per gRPC
+/// method once their response stream ends, API server calls per status
+/// code, HTTP method and host.
+#[derive(Clone, Debug)]
+pub struct Instrumented<S> {
+    inner: S,
+    target: Target,
+}
+
+impl<S> Instrumented<S> {
+    pub fn runtime(inner: S) -> Self {
+        Instrumented { inner, target: Target::Runtime }
+    }
+}
+
+/// Wraps the kube client's HTTP stack in [`Instrumented`].
+#[derive(Clone, Debug)]
+pub struct ApiServerMetricsLayer {
+    host: String,
+}
+
+impl ApiServerMetricsLayer {
+    pub fn new(cluster_url: &http::Uri) -> Self {
+        ApiServerMetricsLayer { host: cluster_url.authority().map(|a| a.to_string()).unwrap_or_default() }
+    }
+}
+
+impl<S> Layer<S> for ApiServerMetricsLayer {
+    type Service = Instrumented<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Instrumented { inner, target: Target::ApiServer { host: self.host.clone() } }
+    }
+}
+
+/// `/runtime.v1.RuntimeService/ListContainers` becomes `list_containers`.
+fn operation_type(path: &str) -> String {
+    let method = path.rsplit('/').next().unwrap_or_default();
+    let mut operation = String::with_capacity(method.len() + 4);
+    for (i, c) in method.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            operation.push('_');
+        }
+        operation.push(c.to_ascii_lowercase());
+    }
+    operation
+}
+
+/// A trailers-only response (how unary errors come back) carries the
+/// status in its headers.
+fn grpc_status(headers: &http::HeaderMap) -> Option<bool> {
+    headers.get("grpc-status").map(|status| status != "0")
+}
+
+/// The Kubernetes verb of an API server request: `get`, `list`, `watch`,
+/// `create`, `update`, `patch`, `delete` or `deletecollection`.
+fn api_verb<B>(request: &http::Request<B>) -> &'static str {
+    let named = names_object(request.uri().path());
+    match *request.method() {
+        http::Method::GET | http::Method::HEAD => {
+            let watch = form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+                .any(|(key, value)| key == "watch" && (value == "true" || value == "1"));
+            if watch {
+                "watch"
+            } else if named {
+                "get"
+            } else {
+                "list"
+            }
+        }
+        http::Method::POST => "create",
+        http::Method::PUT => "update",
+        http::Method::PATCH => "patch",
+        http::Method::DELETE if named => "delete",
+        http::Method::DELETE => "deletecollection",
+        _ => "unknown",
+    }
+}
+
+/// Whether a resource path such as `/api/v1/namespaces/default/pods/web`
+/// names one object rather than a collection. Non-resource paths count as
+/// objects.
+fn names_object(path: &str) -> bool {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let resource = match segments.as_slice() {
+        ["api", _, rest @ ..] | ["apis", _, _, rest @ ..] => rest,
+        _ => return true,
+    };
+    let resource = match resource {
+        ["namespaces", _, rest @ ..] if !rest.is_empty() => rest,
+        _ => resource,
+    };
+    resource.len() >= 2
+}
+
+/// A runtime call still being timed: its outcome is only known once the
+/// response stream ends with the trailers.
+struct PendingOperation {
+    label: String,
+    start: Instant,
+}
+
+impl PendingOperation {
+    fn finish(self, failed: bool) {
+        kubelet::record_runtime_operation(&self.label, self.start.elapsed(), failed);
+    }
+}
+
+/// Response body that records its [`PendingOperation`] at end of stream, or
+/// as failed if it is dropped or errors before then.
+pub struct InstrumentedBody<B> {
+    inner: B,
+    pending: Option<PendingOperation>,
+}
+
+impl<B: Body + Unpin> Body for InstrumentedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Err(_))) = &polled {
+            if let Some(pending) = self.pending.take() {
+                pending.finish(true);
+            }
+        }
+        polled
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let polled = Pin::new(&mut self.inner).poll_trailers(cx);
+        if let Poll::Ready(trailers) = &polled {
+            if let Some(pending) = self.pending.take() {
+                // A stream that ends without a grpc-status is not a success.
+                let failed = trailers.as_ref().map_or(true, |t| t.as_ref().and_then(grpc_status).unwrap_or(true));
+                pending.finish(failed);
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for InstrumentedBody<B> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.finish(true);
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for Instrumented<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<InstrumentedBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let target = self.target.clone();
+        let (label, verb) = match &target {
+            Target::Runtime => (operation_type(request.uri().path()), ""),
+            Target::ApiServer { .. } => (request.method().as_str().to_string(), api_verb(&request)),
+        };
+        let path = request.uri().path().to_string();
+        let start = Instant::now();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let result = response.await;
+            match target {
+                Target::Runtime => {
+                    let pending = PendingOperation { label, start };
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(e) => {
+                            pending.finish(true);
+                            return Err(e);
+                        }
+                    };
+                    // Trailers-only responses end here; everything else
+                    // finishes when the body reaches its trailers.
+                    if let Some(failed) = grpc_status(response.headers()) {
+                        pending.finish(failed);
+                        return Ok(response.map(|inner| InstrumentedBody { inner, pending: None }));
+                    }
+                    Ok(response.map(|inner| InstrumentedBody { inner, pending: Some(pending) }))
+                }
+                Target::ApiServer { host } => {
+                    let code = result.as_ref().map_or("<error>".to_string(), |r| r.status().as_u16().to_string());
+                    kubelet::record_api_request(&code, &label, &host);
+                    debug!("{} {} returned {} after {:?}", verb, path, code, start.elapsed());
+                    result.map(|response| response.map(|inner| InstrumentedBody { inner, pending: None }))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: http::Method, uri: &str) -> http::Request<()> {
+        http::Request::builder().method(method).uri(uri).body(()).unwrap()
+    }
+
+    #[test]
+    fn converts_grpc_methods_to_operation_types() {
+        assert_eq!(operation_type("/runtime.v1.RuntimeService/ListContainers"), "list_containers");
+        assert_eq!(operation_type("/runtime.v1.RuntimeService/RunPodSandbox"), "run_pod_sandbox");
+        assert_eq!(operation_type("/runtime.v1.ImageService/PullImage"), "pull_image");
+        assert_eq!(operation_type("/runtime.v1.RuntimeService/Version"), "version");
+    }
+
+    #[test]
+    fn maps_requests_to_api_verbs() {
+        use http::Method;
+        let cases = [
+            (Method::GET, "/api/v1/namespaces/default/pods/web", "get"),
+            (Method::GET, "/api/v1/namespaces/default/pods", "list"),
+            (Method::GET, "/api/v1/pods?fieldSelector=spec.nodeName%3Dnode", "list"),
+            (Method::GET, "/api/v1/pods?watch=true&resourceVersion=10", "watch"),
+            (Method::GET, "/api/v1/namespaces/default/pods?watch=1", "watch"),
+            (Method::GET, "/api/v1/namespaces/default/pods?watch=false", "list"),
+            (Method::HEAD, "/api/v1/nodes/node", "get"),
+            (Method::POST, "/apis/certificates.k8s.io/v1/certificatesigningrequests", "create"),
+            (Method::PUT, "/apis/coordination.k8s.io/v1/namespaces/kube-node-lease/leases/node", "update"),
+            (Method::PATCH, "/api/v1/namespaces/default/pods/web/status", "patch"),
+            (Method::DELETE, "/api/v1/namespaces/default/pods/web", "delete"),
+            (Method::DELETE, "/api/v1/namespaces/default/pods", "deletecollection"),
+            (Method::OPTIONS, "/api/v1/pods", "unknown"),
+        ];
+        for (method, uri, verb) in cases {
+            assert_eq!(api_verb(&request(method.clone(), uri)), verb, "{} {}", method, uri);
+        }
+    }
+
+    #[test]
+    fn tells_objects_from_collections() {
+        assert!(names_object("/api/v1/nodes/node"));
+        assert!(names_object("/api/v1/namespaces/default"));
+        assert!(names_object("/api/v1/namespaces/default/pods/web"));
+        assert!(names_object("/api/v1/namespaces/default/pods/web/log"));
+        assert!(names_object("/apis/apps/v1/namespaces/default/deployments/web"));
+        assert!(names_object("/version"));
+        assert!(!names_object("/api/v1/namespaces"));
+        assert!(!names_object("/api/v1/pods"));
+        assert!(!names_object("/api/v1/namespaces/default/pods"));
+        assert!(!names_object("/apis/certificates.k8s.io/v1/certificatesigningrequests"));
+    }
+
+    #[test]
+    fn labels_api_requests_with_the_cluster_host() {
+        let layer = ApiServerMetricsLayer::new(&"https://10.0.0.1:6443".parse().unwrap());
+        assert_eq!(layer.host, "10.0.0.1:6443");
+        let layer = ApiServerMetricsLayer::new(&"https://kubernetes.default.svc".parse().unwrap());
+        assert_eq!(layer.host, "kubernetes.default.svc");
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus::{
+    histogram_opts, CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntGauge, Opts, Registry,
+};
+
+use crate::metrics::encode;
+use crate::provider::{cri, get_client};
+
+static METRICS: OnceLock<KubeletMetrics> = OnceLock::new();
+
+/// The kubelet's own operational metrics, served on `/metrics`.
+struct KubeletMetrics {
+    registry: Registry,
+    runtime_operations: CounterVec,
+    runtime_operations_errors: CounterVec,
+    runtime_operations_duration: HistogramVec,
+    pod_start_sli_duration: Histogram,
+    pod_worker_queue_depth: IntGauge,
+    api_requests: CounterVec,
+    lease_renew_duration: Histogram,
+    image_pull_duration: Histogram,
+    running_pods: Gauge,
+    running_containers: GaugeVec,
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(registry: &Registry, collector: T) -> T {
+    registry.register(Box::new(collector.clone())).expect("kubelet metrics are registered once");
+    collector
+}
+
+impl KubeletMetrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let operation = &["operation_type"];
+        KubeletMetrics {
+            runtime_operations: register(&registry, CounterVec::new(
+                Opts::new("kubelet_runtime_operations_total", "Cumulative number of runtime operations by operation type."),
+                operation,
+            ).unwrap()),
+            runtime_operations_errors: register(&registry, CounterVec::new(
+                Opts::new("kubelet_runtime_operations_errors_total", "Cumulative number of runtime operation errors by operation type."),
+                operation,
+            ).unwrap()),
+            runtime_operations_duration: register(&registry, HistogramVec::new(
+                histogram_opts!(
+                    "kubelet_runtime_operations_duration_seconds",
+                    "Duration in seconds of runtime operations. Broken down by operation type.",
+                    prometheus::exponential_buckets(0.005, 2.5, 14).unwrap()
+                ),
+                operation,
+            ).unwrap()),
+            pod_start_sli_duration: register(&registry, Histogram::with_opts(histogram_opts!(
+                "kubelet_pod_start_sli_duration_seconds",
+                "Duration in seconds to start a pod, excluding time to pull images, measured from pod creation timestamp to when all its containers are started.",
+                vec![0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 20.0, 30.0, 45.0, 60.0, 120.0, 180.0, 300.0, 600.0]
+            )).unwrap()),
+            pod_worker_queue_depth: register(&registry, IntGauge::new(
+                "kubelet_pod_worker_queue_depth",
+                "Number of pod sync and teardown operations in progress.",
+            ).unwrap()),
+            api_requests: register(&registry, CounterVec::new(
+                Opts::new("rest_client_requests_total", "Number of HTTP requests, partitioned by status code, method, and host."),
+                &["code", "method", "host"],
+            ).unwrap()),
+            lease_renew_duration: register(&registry, Histogram::with_opts(HistogramOpts::new(
+                "kubelet_node_lease_renew_duration_seconds",
+                "Duration in seconds of node lease renewals.",
+            )).unwrap()),
+            image_pull_duration: register(&registry, Histogram::with_opts(histogram_opts!(
+                "kubelet_image_pull_duration_seconds",
+                "Duration in seconds to pull an image.",
+                vec![1.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 180.0, 240.0, 300.0, 600.0, 900.0, 1200.0]
+            )).unwrap()),
+            running_pods: register(&registry, Gauge::new(
+                "kubelet_running_pods",
+                "Number of pods that have a running pod sandbox.",
+            ).unwrap()),
+            running_containers: register(&registry, GaugeVec::new(
+                Opts::new("kubelet_running_containers", "Number of containers currently running."),
+                &["container_state"],
+            ).unwrap()),
+            registry,
+        }
+    }
+}
+
+fn metrics() -> &'static KubeletMetrics {
+    METRICS.get_or_init(KubeletMetrics::new)
+}
+
+pub fn record_runtime_operation(operation: &str, duration: Duration, failed: bool) {
+    let metrics = metrics();
+    metrics.runtime_operations.with_label_values(&[operation]).inc();
+    metrics.runtime_operations_duration.with_label_values(&[operation]).observe(duration.as_secs_f64());
+    if failed {
+        metrics.runtime_operations_errors.with_label_values(&[operation]).inc();
+    }
+}
+
+pub fn record_api_request(code: &str, method: &str, host: &str) {
+    metrics().api_requests.with_label_values(&[code, method, host]).inc();
+}
+
+pub fn record_pod_start(duration: Duration) {
+    metrics().pod_start_sli_duration.observe(duration.as_secs_f64());
+}
+
+pub fn record_lease_renewal(duration: Duration) {
+    metrics().lease_renew_duration.observe(duration.as_secs_f64());
+}
+
+pub fn record_image_pull(duration: Duration) {
+    metrics().image_pull_duration.observe(duration.as_secs_f64());
+}
+
+/// Counts a pod worker for as long as the returned guard lives.
+pub fn pod_worker() -> PodWorkerGuard {
+    metrics().pod_worker_queue_depth.inc();
+    PodWorkerGuard
+}
+
+pub struct PodWorkerGuard;
+
+impl Drop for PodWorkerGuard {
+    fn drop(&mut self) {
+        metrics().pod_worker_queue_depth.dec();
+    }
+}
+
+/// Refreshes the running pod and container gauges from the runtime.
+async fn update_running_gauges() -> anyhow::Result<()> {
+    let metrics = metrics();
+    let sandboxes = get_client().await
+        .list_pod_sandbox(cri::ListPodSandboxRequest::default())
+        .await?
+        .into_inner()
+        .items;
+    let ready = sandboxes.iter().filter(|s| s.state == cri::PodSandboxState::SandboxReady as i32).count();
+    metrics.running_pods.set(ready as f64);
+
+    let containers = get_client().await
+        .list_containers(cri::ListContainersRequest::default())
+        .await?
+        .into_inner()
+        .containers;
+    metrics.running_containers.reset();
+    for container in containers {
+        let state = match cri::ContainerState::from_i32(container.state) {
+            Some(cri::ContainerState::ContainerCreated) => "created",
+            Some(cri::ContainerState::ContainerRunning) => "running",
+            Some(cri::ContainerState::ContainerExited) => "exited",
+            _ => "unknown",
+        };
+        metrics.running_containers.with_label_values(&[state]).inc();
+    }
+    Ok(())
+}
+
+pub async fn kubelet_metrics() -> anyhow::Result<String> {
+    if let Err(e) = update_running_gauges().await {
+        tracing::warn!("Unable to count running pods and containers: {}", e);
+    }
+    encode(&metrics().registry)
+}
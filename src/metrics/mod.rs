@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use prometheus::core::Collector;
+use prometheus::{CounterVec, Encoder, GaugeVec, Opts, Registry, TextEncoder};
+
+pub mod cadvisor;
+pub mod instrumented;
+pub mod kubelet;
+pub mod resource;
+
+/// Content type of the Prometheus text exposition format.
+pub const TEXT_FORMAT: &str = "text/plain; version=0.0.4";
+
+/// Label pairs of a sample, sorted by name as in gathered metrics.
+type SampleKey = (String, Vec<(String, String)>);
+
+/// A registry filled from freshly collected stats on every scrape.
+struct Scrape {
+    registry: Registry,
+    /// Explicit sample timestamps in milliseconds.
+    timestamps: Mutex<HashMap<SampleKey, i64>>,
+}
+
+impl Scrape {
+    fn new() -> Self {
+        Scrape { registry: Registry::new(), timestamps: Mutex::new(HashMap::new()) }
+    }
+
+    /// Stamps the sample of `collector` with `labels` with a Summary API time.
+    fn stamp(&self, collector: &impl Collector, labels: &[&str], time: &str) {
+        let (Some(desc), Some(millis)) = (collector.desc().first().copied(), epoch_millis(time)) else {
+            return;
+        };
+        let mut pairs: Vec<_> = desc.variable_labels.iter().cloned().zip(labels.iter().map(|l| l.to_string())).collect();
+        pairs.sort();
+        self.timestamps.lock().unwrap().insert((desc.fq_name.clone(), pairs), millis);
+    }
+
+    fn gauge(&self, name: &str, help: &str, labels: &[&str]) -> anyhow::Result<GaugeVec> {
+        let gauge = GaugeVec::new(Opts::new(name, help), labels)?;
+        self.registry.register(Box::new(gauge.clone()))?;
+        Ok(gauge)
+    }
+
+    fn counter(&self, name: &str, help: &str, labels: &[&str]) -> anyhow::Result<CounterVec> {
+        let counter = CounterVec::new(Opts::new(name, help), labels)?;
+        self.registry.register(Box::new(counter.clone()))?;
+        Ok(counter)
+    }
+
+    fn encode(self) -> anyhow::Result<String> {
+        let timestamps = self.timestamps.into_inner().unwrap();
+        let mut families = self.registry.gather();
+        for family in &mut families {
+            let name = family.get_name().to_string();
+            for metric in family.mut_metric().iter_mut() {
+                let pairs = metric.get_label().iter().map(|l| (l.get_name().to_string(), l.get_value().to_string())).collect();
+                if let Some(&millis) = timestamps.get(&(name.clone(), pairs)) {
+                    metric.set_timestamp_ms(millis);
+                }
+            }
+        }
+        encode_families(&families)
+    }
+}
+
+pub fn encode(registry: &Registry) -> anyhow::Result<String> {
+    encode_families(&registry.gather())
+}
+
+fn encode_families(families: &[prometheus::proto::MetricFamily]) -> anyhow::Result<String> {
+    let mut buf = vec![];
+    TextEncoder::new().encode(families, &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn seconds(nanos: u64) -> f64 {
+    nanos as f64 / 1e9
+}
+
+/// Parses a Summary API timestamp back into seconds since the epoch.
+fn epoch_seconds(time: &str) -> Option<f64> {
+    chrono::DateTime::parse_from_rfc3339(time).ok().map(|t| t.timestamp() as f64)
+}
+
+fn epoch_millis(time: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(time).ok().map(|t| t.timestamp_millis())
+}
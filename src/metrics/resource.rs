@@ -0,0 +1,94 @@
+use tracing::*;
+
+use crate::metrics::{epoch_seconds, seconds, Scrape};
+use crate::stats;
+
+/// `/metrics/resource`: the CPU and memory figures the metrics server and
+/// the scheduler's resource pipeline consume. Samples carry the time the
+/// runtime took the stats, as upstream.
+pub async fn resource_metrics() -> anyhow::Result<String> {
+    let scrape = Scrape::new();
+    let scrape_error = scrape.gauge("scrape_error", "1 if there was an error while getting container metrics, 0 otherwise", &[])?;
+    let node_cpu = scrape.counter("node_cpu_usage_seconds_total", "Cumulative cpu time consumed by the node in core-seconds", &[])?;
+    let node_memory = scrape.gauge("node_memory_working_set_bytes", "Current working set of the node in bytes", &[])?;
+    let pod_labels = ["namespace", "pod"];
+    let pod_cpu = scrape.counter("pod_cpu_usage_seconds_total", "Cumulative cpu time consumed by the pod in core-seconds", &pod_labels)?;
+    let pod_memory = scrape.gauge("pod_memory_working_set_bytes", "Current working set of the pod in bytes", &pod_labels)?;
+    let container_labels = ["container", "namespace", "pod"];
+    let container_cpu = scrape.counter(
+        "container_cpu_usage_seconds_total",
+        "Cumulative cpu time consumed by the container in core-seconds",
+        &container_labels,
+    )?;
+    let container_memory = scrape.gauge(
+        "container_memory_working_set_bytes",
+        "Current working set of the container in bytes",
+        &container_labels,
+    )?;
+    let container_start = scrape.gauge(
+        "container_start_time_seconds",
+        "Start time of the container since unix epoch in seconds",
+        &container_labels,
+    )?;
+
+    let summary = match stats::summary(true).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            warn!("Unable to collect resource metrics: {}", e);
+            scrape_error.with_label_values(&[]).set(1.0);
+            return scrape.encode();
+        }
+    };
+    scrape_error.with_label_values(&[]).set(0.0);
+    if let Some(cpu) = &summary.node.cpu {
+        if let Some(usage) = cpu.usage_core_nano_seconds {
+            node_cpu.with_label_values(&[]).inc_by(seconds(usage));
+            scrape.stamp(&node_cpu, &[], &cpu.time);
+        }
+    }
+    if let Some(memory) = &summary.node.memory {
+        if let Some(working_set) = memory.working_set_bytes {
+            node_memory.with_label_values(&[]).set(working_set as f64);
+            scrape.stamp(&node_memory, &[], &memory.time);
+        }
+    }
+    for pod in &summary.pods {
+        let (namespace, name) = (pod.pod_ref.namespace.as_str(), pod.pod_ref.name.as_str());
+        let labels = [namespace, name];
+        if let Some(cpu) = &pod.cpu {
+            if let Some(usage) = cpu.usage_core_nano_seconds {
+                pod_cpu.with_label_values(&labels).inc_by(seconds(usage));
+                scrape.stamp(&pod_cpu, &labels, &cpu.time);
+            }
+        }
+        if let Some(memory) = &pod.memory {
+            if let Some(working_set) = memory.working_set_bytes {
+                pod_memory.with_label_values(&labels).set(working_set as f64);
+                scrape.stamp(&pod_memory, &labels, &memory.time);
+            }
+        }
+        for container in &pod.containers {
+            let labels = [container.name.as_str(), namespace, name];
+            if let Some(cpu) = &container.cpu {
+                if let Some(usage) = cpu.usage_core_nano_seconds {
+                    container_cpu.with_label_values(&labels).inc_by(seconds(usage));
+                    scrape.stamp(&container_cpu, &labels, &cpu.time);
+                }
+            }
+            if let Some(memory) = &container.memory {
+                if let Some(working_set) = memory.working_set_bytes {
+                    container_memory.with_label_values(&labels).set(working_set as f64);
+                    scrape.stamp(&container_memory, &labels, &memory.time);
+                }
+            }
+            if let Some(start) = epoch_seconds(&container.start_time) {
+                container_start.with_label_values(&labels).set(start);
+                // Upstream stamps the start time with the CPU sample's time.
+                if let Some(cpu) = &container.cpu {
+                    scrape.stamp(&container_start, &labels, &cpu.time);
+                }
+            }
+        }
+    }
+    scrape.encode()
+}
@@ -0,0 +1,15 @@
+use std::net::{IpAddr, UdpSocket};
+
+use crate::kubelet::config::config;
+
+/// The node's primary IP: `nodeIP` from the config, otherwise the address of
+/// the interface that carries the default route.
+pub fn node_ip() -> Option<IpAddr> {
+    if let Some(ip) = &config().node_ip {
+        return ip.parse().ok();
+    }
+    // Connecting a UDP socket sends nothing but selects the outbound interface.
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:53").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
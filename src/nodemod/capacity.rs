@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use nix::sys::statvfs::statvfs;
+use tracing::*;
+
+use crate::kubelet::config::{config, KubeletConfig};
+use crate::provider::quantity::{self, ParsedQuantity};
+use crate::provider::qos;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const HUGEPAGES_DIR: &str = "/sys/kernel/mm/hugepages";
+/// Eviction signals that reduce allocatable, and the resource they guard.
+const EVICTION_SIGNALS: &[(&str, &str)] = &[("memory.available", "memory"), ("nodefs.available", "ephemeral-storage")];
+
+/// Node resources as plain amounts: millicores for `cpu`, bytes or counts
+/// for everything else.
+pub type Resources = BTreeMap<String, i64>;
+
+/// What the machine has, as far as the kubelet's cgroup lets it use.
+pub fn capacity() -> Resources {
+    let mut capacity = Resources::new();
+    capacity.insert("cpu".to_string(), cpu_millis());
+    capacity.insert("memory".to_string(), memory_bytes());
+    capacity.extend(hugepages());
+    // The root dir may not exist yet; its nearest existing ancestor is on the same filesystem.
+    let root_fs = Path::new(&config().root_dir).ancestors().find_map(|dir| statvfs(dir).ok());
+    if let Some(vfs) = root_fs {
+        capacity.insert("ephemeral-storage".to_string(), (vfs.blocks() * vfs.fragment_size()) as i64);
+    }
+    capacity.insert("pods".to_string(), config().max_pods as i64);
+    capacity
+}
+
+/// Capacity minus `kubeReserved`, `systemReserved` and the hard eviction
+/// thresholds; memory also loses what is pre-allocated to huge pages.
+pub fn allocatable(capacity: &Resources) -> anyhow::Result<Resources> {
+    allocatable_for(capacity, config())
+}
+
+fn allocatable_for(capacity: &Resources, config: &KubeletConfig) -> anyhow::Result<Resources> {
+    let mut allocatable = capacity.clone();
+    for reserved in [&config.kube_reserved, &config.system_reserved] {
+        for (resource, value) in reserved {
+            let reserved = amount(resource, &quantity::parse(value)?);
+            if let Some(available) = allocatable.get_mut(resource) {
+                *available -= reserved;
+            }
+        }
+    }
+    for (signal, resource) in EVICTION_SIGNALS {
+        if let (Some(threshold), Some(total)) = (config.eviction_hard.get(*signal), capacity.get(*resource)) {
+            *allocatable.get_mut(*resource).unwrap() -= threshold_amount(threshold, *total)?;
+        }
+    }
+    let hugepages: i64 = capacity.iter().filter(|(name, _)| name.starts_with("hugepages-")).map(|(_, v)| v).sum();
+    if let Some(memory) = allocatable.get_mut("memory") {
+        *memory -= hugepages;
+    }
+    for available in allocatable.values_mut() {
+        *available = (*available).max(0);
+    }
+    Ok(allocatable)
+}
+
+/// Capacity and allocatable as node status quantities. Invalid reservations
+/// are logged and leave allocatable equal to capacity.
+pub fn node_resources() -> (BTreeMap<String, Quantity>, BTreeMap<String, Quantity>) {
+    let capacity = capacity();
+    let allocatable = allocatable(&capacity).unwrap_or_else(|e| {
+        error!("Unable to compute allocatable resources: {}", e);
+        capacity.clone()
+    });
+    (to_quantities(&capacity), to_quantities(&allocatable))
+}
+
+fn to_quantities(resources: &Resources) -> BTreeMap<String, Quantity> {
+    resources
+        .iter()
+        .map(|(name, &amount)| {
+            let value = match name.as_str() {
+                "cpu" if amount % 1000 == 0 => (amount / 1000).to_string(),
+                "cpu" => format!("{}m", amount),
+                _ => amount.to_string(),
+            };
+            (name.clone(), Quantity(value))
+        })
+        .collect()
+}
+
+fn amount(resource: &str, quantity: &ParsedQuantity) -> i64 {
+    if resource == "cpu" {
+        quantity.milli_value()
+    } else {
+        quantity.value()
+    }
+}
+
+/// A threshold such as `100Mi`, or `10%` of `total`.
+fn threshold_amount(threshold: &str, total: i64) -> anyhow::Result<i64> {
+    match threshold.strip_suffix('%') {
+        Some(percent) => Ok((total as f64 * percent.trim().parse::<f64>()? / 100.0) as i64),
+        None => Ok(quantity::parse(threshold)?.value()),
+    }
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn cgroup_v2() -> bool {
+    Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+}
+
+/// Online CPUs from `/proc/cpuinfo`, narrowed by the cpuset and CFS quota.
+fn cpu_millis() -> i64 {
+    let processors = std::fs::read_to_string("/proc/cpuinfo")
+        .map(|info| info.lines().filter(|line| line.starts_with("processor")).count())
+        .unwrap_or(0);
+    let processors = if processors > 0 {
+        processors
+    } else {
+        std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    };
+    let mut millis = processors as i64 * 1000;
+    if let Some(cpus) = cgroup_cpuset() {
+        millis = millis.min(cpus as i64 * 1000);
+    }
+    if let Some(quota) = cgroup_cpu_quota_millis() {
+        millis = millis.min(quota);
+    }
+    millis
+}
+
+fn cgroup_cpuset() -> Option<usize> {
+    let root = Path::new(CGROUP_ROOT);
+    let list = if cgroup_v2() {
+        read_trimmed(root.join("cpuset.cpus.effective"))
+    } else {
+        read_trimmed(root.join("cpuset/cpuset.effective_cpus")).or_else(|| read_trimmed(root.join("cpuset/cpuset.cpus")))
+    }?;
+    let count = cpu_list_len(&list)?;
+    (count > 0).then_some(count)
+}
+
+/// Number of CPUs in a list such as `0-3,6`.
+fn cpu_list_len(list: &str) -> Option<usize> {
+    list.split(',').filter(|part| !part.is_empty()).try_fold(0, |count, part| {
+        let len = match part.split_once('-') {
+            Some((first, last)) => last.parse::<usize>().ok()?.checked_sub(first.parse::<usize>().ok()?)? + 1,
+            None => part.parse::<usize>().map(|_| 1).ok()?,
+        };
+        Some(count + len)
+    })
+}
+
+fn cgroup_cpu_quota_millis() -> Option<i64> {
+    let root = Path::new(CGROUP_ROOT);
+    let (quota, period) = if cgroup_v2() {
+        let max = read_trimmed(root.join("cpu.max"))?;
+        let (quota, period) = max.split_once(' ')?;
+        (quota.parse::<i64>().ok()?, period.parse::<i64>().ok()?)
+    } else {
+        (
+            read_trimmed(root.join("cpu/cpu.cfs_quota_us"))?.parse::<i64>().ok()?,
+            read_trimmed(root.join("cpu/cpu.cfs_period_us"))?.parse::<i64>().ok()?,
+        )
+    };
+    // "max" fails to parse above and -1 means no quota on cgroup v1.
+    (quota > 0 && period > 0).then(|| quota * 1000 / period)
+}
+
+/// `MemTotal`, capped by the cgroup memory limit.
+fn memory_bytes() -> i64 {
+    let total = qos::machine_memory_capacity();
+    let root = Path::new(CGROUP_ROOT);
+    let limit = if cgroup_v2() {
+        read_trimmed(root.join("memory.max"))
+    } else {
+        read_trimmed(root.join("memory/memory.limit_in_bytes"))
+    };
+    match limit.and_then(|limit| limit.parse::<i64>().ok()) {
+        Some(limit) if limit > 0 && (total == 0 || limit < total) => limit,
+        _ => total,
+    }
+}
+
+/// Pre-allocated huge pages per page size, e.g. `hugepages-2Mi`, in bytes.
+fn hugepages() -> Resources {
+    let Ok(entries) = std::fs::read_dir(HUGEPAGES_DIR) else {
+        return Resources::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let kb: i64 = name.strip_prefix("hugepages-")?.strip_suffix("kB")?.parse().ok()?;
+            let pages: i64 = read_trimmed(entry.path().join("nr_hugepages"))?.parse().ok()?;
+            let size = if kb % (1024 * 1024) == 0 {
+                format!("{}Gi", kb / (1024 * 1024))
+            } else if kb % 1024 == 0 {
+                format!("{}Mi", kb / 1024)
+            } else {
+                format!("{}Ki", kb)
+            };
+            Some((format!("hugepages-{}", size), pages * kb * 1024))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    const GI: i64 = 1024 * 1024 * 1024;
+
+    fn resources(entries: &[(&str, i64)]) -> Resources {
+        entries.iter().map(|(name, amount)| (name.to_string(), *amount)).collect()
+    }
+
+    fn settings(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn counts_cpu_lists() {
+        assert_eq!(cpu_list_len("0-3,6"), Some(5));
+        assert_eq!(cpu_list_len("7"), Some(1));
+        assert_eq!(cpu_list_len(""), Some(0));
+        assert_eq!(cpu_list_len("3-0"), None);
+        assert_eq!(cpu_list_len("a-b"), None);
+    }
+
+    #[test]
+    fn parses_thresholds() {
+        assert_eq!(threshold_amount("10%", 1000).unwrap(), 100);
+        assert_eq!(threshold_amount("100Mi", 1000).unwrap(), 100 * 1024 * 1024);
+        assert!(threshold_amount("ten%", 1000).is_err());
+    }
+
+    #[test]
+    fn subtracts_reservations_and_thresholds() {
+        let config = KubeletConfig {
+            kube_reserved: settings(&[("cpu", "100m"), ("memory", "1Gi")]),
+            system_reserved: settings(&[("cpu", "400m")]),
+            eviction_hard: settings(&[("memory.available", "1Gi"), ("nodefs.available", "10%")]),
+            ..Default::default()
+        };
+        let capacity =
+            resources(&[("cpu", 4000), ("memory", 8 * GI), ("ephemeral-storage", 100 * GI), ("pods", 110)]);
+        let allocatable = allocatable_for(&capacity, &config).unwrap();
+        assert_eq!(
+            allocatable,
+            resources(&[("cpu", 3500), ("memory", 6 * GI), ("ephemeral-storage", 90 * GI), ("pods", 110)])
+        );
+    }
+
+    #[test]
+    fn clamps_allocatable_at_zero() {
+        let config = KubeletConfig {
+            kube_reserved: settings(&[("cpu", "2")]),
+            eviction_hard: BTreeMap::new(),
+            ..Default::default()
+        };
+        let allocatable = allocatable_for(&resources(&[("cpu", 1000)]), &config).unwrap();
+        assert_eq!(allocatable["cpu"], 0);
+    }
+
+    #[test]
+    fn subtracts_hugepages_from_memory() {
+        let config = KubeletConfig { eviction_hard: BTreeMap::new(), ..Default::default() };
+        let capacity = resources(&[("memory", 4 * GI), ("hugepages-2Mi", GI), ("hugepages-1Gi", GI)]);
+        let allocatable = allocatable_for(&capacity, &config).unwrap();
+        assert_eq!(allocatable["memory"], 2 * GI);
+        assert_eq!(allocatable["hugepages-2Mi"], GI);
+    }
+
+    #[test]
+    fn rejects_invalid_reservations() {
+        let config = KubeletConfig { kube_reserved: settings(&[("cpu", "lots")]), ..Default::default() };
+        assert!(allocatable_for(&resources(&[("cpu", 1000)]), &config).is_err());
+    }
+}
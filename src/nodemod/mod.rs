@@ -1 +1,3 @@
+pub mod address;
+pub mod capacity;
 pub mod node;
\ No newline at end of file
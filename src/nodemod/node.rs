@@ -23,6 +23,9 @@ impl Builder {
     pub fn add_label(&mut self, key: &str, value: &str) {
         self.labels.insert(key.to_string(), value.to_string());
     }
+    pub fn set_port(&mut self, port: i32) {
+        self.port = port;
+    }
     pub fn add_capacity(&mut self, key: &str, value: &str) {
         self.capacity.insert(
             key.to_string(),
@@ -30,6 +33,13 @@ impl Builder {
         );
     }
 
+    pub fn add_allocatable(&mut self, key: &str, value: &str) {
+        self.allocatable.insert(
+            key.to_string(),
+            k8s_openapi::apimachinery::pkg::api::resource::Quantity(value.to_string()),
+        );
+    }
+
     pub fn build(self) -> Node {
         let metadata = k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
             name: Some(self.name),
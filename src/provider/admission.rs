@@ -0,0 +1,70 @@
+use std::sync::OnceLock;
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, ResourceExt};
+use kube::api::PatchParams;
+use tracing::*;
+
+use crate::kubelet::client;
+use crate::kubelet::config::config;
+use crate::provider::{pod_manager, ports, sysctl};
+
+static SYSCTL_ALLOWLIST: OnceLock<sysctl::PatternAllowlist> = OnceLock::new();
+
+/// Validates the admission settings from the kubelet config.
+pub fn init() -> anyhow::Result<()> {
+    let allowlist = sysctl::PatternAllowlist::new(&config().allowed_unsafe_sysctls)?;
+    let _ = SYSCTL_ALLOWLIST.set(allowlist);
+    Ok(())
+}
+
+/// Why the kubelet refused to run a pod.
+#[derive(Debug)]
+pub struct Rejection {
+    pub reason: String,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(reason: &str, message: String) -> Self {
+        Rejection { reason: reason.to_string(), message }
+    }
+}
+
+/// Runs the node-local admission checks for a pod before anything is created
+/// for it, and registers the pod with the pod manager when they pass.
+pub fn admit(pod: &Pod) -> Result<(), Rejection> {
+    let allowlist = SYSCTL_ALLOWLIST.get().expect("admission not initialised");
+    allowlist
+        .admit(pod)
+        .map_err(|message| Rejection::new(sysctl::FORBIDDEN_REASON, message))?;
+    pod_manager::try_add(pod, |running| {
+        ports::check_host_ports(pod, running)
+            .map_err(|message| Rejection::new(ports::CONFLICT_REASON, message))
+    })
+}
+
+/// Marks a rejected pod as failed so that its controller can replace it.
+pub async fn reject_pod(pod: &Pod, rejection: &Rejection) {
+    pod_manager::remove(&pod.uid().unwrap_or_default());
+    warn!("pod {} rejected: {}: {}", pod.name_any(), rejection.reason, rejection.message);
+    let client = client::client().await.unwrap();
+    let pod_client: Api<Pod> = Api::namespaced(client, &pod.namespace().unwrap_or_default());
+    let status_patch = serde_json::json!({
+        "status": {
+            "phase": "Failed",
+            "reason": rejection.reason,
+            "message": format!("Pod was rejected: {}", rejection.message),
+        }
+    });
+    if let Err(e) = pod_client
+        .patch_status(
+            &pod.name_any(),
+            &PatchParams::default(),
+            &kube::api::Patch::Strategic(status_patch),
+        )
+        .await
+    {
+        error!("Unable to patch status of rejected pod {}: {}", pod.name_any(), e);
+    }
+}
@@ -0,0 +1,308 @@
+use k8s_openapi::api::core::v1::{Pod, PodDNSConfig};
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::provider::{cri, namespaces};
+
+const MAX_DNS_NAMESERVERS: usize = 3;
+const MAX_DNS_SEARCH_PATHS: usize = 32;
+const MAX_DNS_SEARCH_LIST_CHARS: usize = 2048;
+const DEFAULT_DNS_OPTIONS: &[&str] = &["ndots:5"];
+
+#[derive(Debug, PartialEq, Eq)]
+enum PodDnsType {
+    Cluster,
+    Host,
+    None,
+}
+
+fn pod_dns_type(pod: &Pod) -> PodDnsType {
+    let policy = pod.spec.as_ref().and_then(|s| s.dns_policy.as_deref()).unwrap_or("ClusterFirst");
+    match policy {
+        "None" => PodDnsType::None,
+        "ClusterFirstWithHostNet" => PodDnsType::Cluster,
+        "ClusterFirst" if !namespaces::host_network(pod) => PodDnsType::Cluster,
+        // ClusterFirst on the host network behaves like Default.
+        _ => PodDnsType::Host,
+    }
+}
+
+/// Reads nameservers, search domains and options from a resolv.conf file.
+fn parse_resolv_conf(content: &str) -> cri::DnsConfig {
+    let mut dns = cri::DnsConfig::default();
+    for line in content.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => dns.servers.extend(fields.next().map(str::to_string)),
+            // The last search line wins, as in the resolver itself.
+            Some("search") => {
+                dns.searches = fields.map(|s| s.trim_end_matches('.').to_string()).collect()
+            }
+            Some("options") => dns.options.extend(fields.map(str::to_string)),
+            _ => {}
+        }
+    }
+    dns
+}
+
+fn host_dns_config(resolv_conf: &str) -> cri::DnsConfig {
+    if resolv_conf.is_empty() {
+        return cri::DnsConfig::default();
+    }
+    match std::fs::read_to_string(resolv_conf) {
+        Ok(content) => parse_resolv_conf(&content),
+        Err(e) => {
+            warn!("Unable to read resolv.conf {}: {}", resolv_conf, e);
+            cri::DnsConfig::default()
+        }
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, items: impl IntoIterator<Item = String>) {
+    for item in items {
+        if !list.contains(&item) {
+            list.push(item);
+        }
+    }
+}
+
+fn cluster_searches(host_searches: &[String], namespace: &str, cluster_domain: &str) -> Vec<String> {
+    if cluster_domain.is_empty() {
+        return host_searches.to_vec();
+    }
+    let mut searches = vec![
+        format!("{}.svc.{}", namespace, cluster_domain),
+        format!("svc.{}", cluster_domain),
+        cluster_domain.to_string(),
+    ];
+    push_unique(&mut searches, host_searches.iter().cloned());
+    searches
+}
+
+/// Options are merged by name, with values from the pod taking precedence.
+fn merge_options(existing: &[String], pod_dns: &PodDNSConfig) -> Vec<String> {
+    let mut options: Vec<(String, Option<String>)> = existing
+        .iter()
+        .map(|o| match o.split_once(':') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (o.clone(), None),
+        })
+        .collect();
+    for option in pod_dns.options.iter().flatten() {
+        let Some(name) = option.name.clone() else { continue };
+        match options.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = option.value.clone(),
+            None => options.push((name, option.value.clone())),
+        }
+    }
+    options
+        .into_iter()
+        .map(|(name, value)| match value {
+            Some(value) => format!("{}:{}", name, value),
+            None => name,
+        })
+        .collect()
+}
+
+fn append_pod_dns_config(mut dns: cri::DnsConfig, pod_dns: &PodDNSConfig) -> cri::DnsConfig {
+    push_unique(&mut dns.servers, pod_dns.nameservers.clone().unwrap_or_default());
+    push_unique(&mut dns.searches, pod_dns.searches.clone().unwrap_or_default());
+    dns.options = merge_options(&dns.options, pod_dns);
+    dns
+}
+
+/// Trims the nameserver and search lists to what the resolver supports.
+fn fit_limits(mut dns: cri::DnsConfig, pod_name: &str) -> cri::DnsConfig {
+    if dns.servers.len() > MAX_DNS_NAMESERVERS {
+        warn!("Nameserver limits exceeded for pod {}, only the first {} are applied", pod_name, MAX_DNS_NAMESERVERS);
+        dns.servers.truncate(MAX_DNS_NAMESERVERS);
+    }
+    let mut searches: Vec<String> = vec![];
+    let mut chars = 0;
+    for search in dns.searches.drain(..) {
+        if searches.len() == MAX_DNS_SEARCH_PATHS || chars + search.len() > MAX_DNS_SEARCH_LIST_CHARS {
+            warn!("Search line limits exceeded for pod {}, some search paths were omitted", pod_name);
+            break;
+        }
+        chars += search.len() + 1;
+        searches.push(search);
+    }
+    dns.searches = searches;
+    dns
+}
+
+/// Builds the sandbox DNS configuration from `dnsPolicy`, `dnsConfig` and the
+/// kubelet's cluster DNS settings.
+pub fn pod_dns_config(pod: &Pod) -> cri::DnsConfig {
+    let config = config();
+    build_dns_config(pod, host_dns_config(&config.resolv_conf), &config.cluster_dns, &config.cluster_domain)
+}
+
+fn build_dns_config(
+    pod: &Pod,
+    mut dns: cri::DnsConfig,
+    cluster_dns: &[String],
+    cluster_domain: &str,
+) -> cri::DnsConfig {
+    let pod_name = pod.metadata.name.clone().unwrap_or_default();
+    let namespace = pod.metadata.namespace.clone().unwrap_or_default();
+
+    let mut dns_type = pod_dns_type(pod);
+    if dns_type == PodDnsType::Cluster && cluster_dns.is_empty() {
+        warn!("pod {} has dnsPolicy ClusterFirst but clusterDNS is not configured, falling back to Default", pod_name);
+        dns_type = PodDnsType::Host;
+    }
+    match dns_type {
+        PodDnsType::None => dns = cri::DnsConfig::default(),
+        PodDnsType::Cluster => {
+            dns.servers = cluster_dns.to_vec();
+            dns.searches = cluster_searches(&dns.searches, &namespace, cluster_domain);
+            dns.options = DEFAULT_DNS_OPTIONS.iter().map(|o| o.to_string()).collect();
+        }
+        PodDnsType::Host => {}
+    }
+
+    if let Some(pod_dns) = pod.spec.as_ref().and_then(|s| s.dns_config.as_ref()) {
+        dns = append_pod_dns_config(dns, pod_dns);
+    }
+    fit_limits(dns, &pod_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodDNSConfigOption, PodSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn dns(servers: &[&str], searches: &[&str], options: &[&str]) -> cri::DnsConfig {
+        cri::DnsConfig { servers: strings(servers), searches: strings(searches), options: strings(options) }
+    }
+
+    #[test]
+    fn parses_resolv_conf() {
+        let cases = [
+            ("", dns(&[], &[], &[])),
+            (
+                "nameserver 1.1.1.1\nnameserver 8.8.8.8\nsearch corp.example. example.com\noptions ndots:2 rotate\n",
+                dns(&["1.1.1.1", "8.8.8.8"], &["corp.example", "example.com"], &["ndots:2", "rotate"]),
+            ),
+            (
+                "# generated\nnameserver 10.0.0.10 # primary\n; comment\ndomain local\n  nameserver\t10.0.0.11\n",
+                dns(&["10.0.0.10", "10.0.0.11"], &[], &[]),
+            ),
+            ("search a.example\nsearch b.example c.example\n", dns(&[], &["b.example", "c.example"], &[])),
+            ("options timeout:1\noptions attempts:2\n", dns(&[], &[], &["timeout:1", "attempts:2"])),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(parse_resolv_conf(content), expected, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn builds_cluster_search_paths() {
+        let host = strings(&["example.com", "svc.cluster.local"]);
+        assert_eq!(
+            cluster_searches(&host, "ns", "cluster.local"),
+            strings(&["ns.svc.cluster.local", "svc.cluster.local", "cluster.local", "example.com"])
+        );
+        assert_eq!(cluster_searches(&host, "ns", ""), host);
+    }
+
+    #[test]
+    fn trims_nameservers_and_search_paths() {
+        let trimmed = fit_limits(dns(&["1.1.1.1", "2.2.2.2", "3.3.3.3", "4.4.4.4"], &[], &[]), "p");
+        assert_eq!(trimmed.servers, strings(&["1.1.1.1", "2.2.2.2", "3.3.3.3"]));
+
+        let searches: Vec<String> = (0..40).map(|i| format!("d{}.example", i)).collect();
+        let trimmed = fit_limits(cri::DnsConfig { searches: searches.clone(), ..Default::default() }, "p");
+        assert_eq!(trimmed.searches, searches[..MAX_DNS_SEARCH_PATHS].to_vec());
+
+        let long: Vec<String> = (0..5).map(|i| format!("{}{}", i, "x".repeat(499))).collect();
+        let trimmed = fit_limits(cri::DnsConfig { searches: long.clone(), ..Default::default() }, "p");
+        assert_eq!(trimmed.searches, long[..4].to_vec());
+    }
+
+    fn pod(policy: Option<&str>, host_network: bool, dns_config: Option<PodDNSConfig>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("p".to_string()),
+                namespace: Some("ns".to_string()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                dns_policy: policy.map(str::to_string),
+                host_network: Some(host_network),
+                dns_config,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn applies_dns_policy() {
+        let host = dns(&["192.168.0.1"], &["example.com"], &["rotate"]);
+        let cluster_dns = strings(&["10.96.0.10"]);
+        let cluster = dns(
+            &["10.96.0.10"],
+            &["ns.svc.cluster.local", "svc.cluster.local", "cluster.local", "example.com"],
+            &["ndots:5"],
+        );
+        let cases = [
+            ("default policy", pod(None, false, None), cluster_dns.clone(), cluster.clone()),
+            ("ClusterFirst", pod(Some("ClusterFirst"), false, None), cluster_dns.clone(), cluster.clone()),
+            ("ClusterFirst on host network", pod(Some("ClusterFirst"), true, None), cluster_dns.clone(), host.clone()),
+            (
+                "ClusterFirstWithHostNet",
+                pod(Some("ClusterFirstWithHostNet"), true, None),
+                cluster_dns.clone(),
+                cluster.clone(),
+            ),
+            ("ClusterFirst without clusterDNS", pod(Some("ClusterFirst"), false, None), vec![], host.clone()),
+            ("Default", pod(Some("Default"), false, None), cluster_dns.clone(), host.clone()),
+            ("None", pod(Some("None"), false, None), cluster_dns.clone(), dns(&[], &[], &[])),
+        ];
+        for (name, pod, cluster_dns, expected) in cases {
+            assert_eq!(build_dns_config(&pod, host.clone(), &cluster_dns, "cluster.local"), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn merges_pod_dns_config() {
+        let host = dns(&["192.168.0.1"], &["example.com"], &["rotate"]);
+        let option = |name: &str, value: Option<&str>| PodDNSConfigOption {
+            name: Some(name.to_string()),
+            value: value.map(str::to_string),
+        };
+        let pod_dns = PodDNSConfig {
+            nameservers: Some(strings(&["10.96.0.10", "1.1.1.1"])),
+            searches: Some(strings(&["svc.cluster.local", "my.dns.search"])),
+            options: Some(vec![option("ndots", Some("2")), option("edns0", None)]),
+        };
+        let merged = build_dns_config(
+            &pod(Some("ClusterFirst"), false, Some(pod_dns.clone())),
+            host.clone(),
+            &strings(&["10.96.0.10"]),
+            "cluster.local",
+        );
+        assert_eq!(
+            merged,
+            dns(
+                &["10.96.0.10", "1.1.1.1"],
+                &["ns.svc.cluster.local", "svc.cluster.local", "cluster.local", "example.com", "my.dns.search"],
+                &["ndots:2", "edns0"],
+            )
+        );
+
+        let none = build_dns_config(&pod(Some("None"), false, Some(pod_dns)), host, &[], "cluster.local");
+        assert_eq!(
+            none,
+            dns(&["10.96.0.10", "1.1.1.1"], &["svc.cluster.local", "my.dns.search"], &["ndots:2", "edns0"])
+        );
+    }
+}
@@ -0,0 +1,651 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{ConfigMap, Container, EnvVarSource, Pod, ResourceFieldSelector, Secret, Service};
+use kube::{Api, ResourceExt};
+use tracing::*;
+
+use crate::kubelet::client;
+use crate::nodemod::address::node_ip;
+use crate::nodemod::capacity;
+use crate::provider::cri;
+use crate::provider::quantity::{self, parse_quantity};
+
+const MASTER_SERVICE_NAMESPACE: &str = "default";
+const MASTER_SERVICE_NAME: &str = "kubernetes";
+
+/// Selects the services whose variables are injected into a pod in `namespace`.
+/// The `kubernetes` service from the default namespace is always included; a
+/// service with the same name in the pod's own namespace takes its place.
+pub fn service_env_map(
+    services: &[Service],
+    namespace: &str,
+    enable_service_links: bool,
+) -> BTreeMap<String, String> {
+    let mut selected: BTreeMap<String, &Service> = BTreeMap::new();
+    for service in services {
+        if !is_service_ip_set(service) {
+            continue;
+        }
+        let name = service.metadata.name.clone().unwrap_or_default();
+        let ns = service.metadata.namespace.as_deref().unwrap_or_default();
+        if ns == MASTER_SERVICE_NAMESPACE && name == MASTER_SERVICE_NAME {
+            selected.entry(name).or_insert(service);
+        } else if ns == namespace && enable_service_links {
+            selected.insert(name, service);
+        }
+    }
+    let services: Vec<&Service> = selected.into_values().collect();
+    from_services(&services)
+}
+
+/// Builds the `{SVC}_SERVICE_HOST`/`{SVC}_SERVICE_PORT` and Docker link style
+/// variables for the given services.
+pub fn from_services(services: &[&Service]) -> BTreeMap<String, String> {
+    let mut result = BTreeMap::new();
+    for service in services {
+        if !is_service_ip_set(service) {
+            continue;
+        }
+        let spec = service.spec.as_ref().unwrap();
+        let ip = spec.cluster_ip.clone().unwrap_or_default();
+        let ports = spec.ports.clone().unwrap_or_default();
+        let name = make_env_variable_name(&service.metadata.name.clone().unwrap_or_default());
+
+        result.insert(format!("{}_SERVICE_HOST", name), ip.clone());
+        if let Some(first) = ports.first() {
+            result.insert(format!("{}_SERVICE_PORT", name), first.port.to_string());
+        }
+        for port in &ports {
+            if let Some(port_name) = port.name.as_deref().filter(|n| !n.is_empty()) {
+                result.insert(
+                    format!("{}_SERVICE_PORT_{}", name, make_env_variable_name(port_name)),
+                    port.port.to_string(),
+                );
+            }
+        }
+        result.extend(make_link_variables(&name, &ip, &ports));
+    }
+    result
+}
+
+fn make_link_variables(
+    name: &str,
+    ip: &str,
+    ports: &[k8s_openapi::api::core::v1::ServicePort],
+) -> BTreeMap<String, String> {
+    let host = if ip.contains(':') { format!("[{}]", ip) } else { ip.to_string() };
+    let mut result = BTreeMap::new();
+    for (i, port) in ports.iter().enumerate() {
+        let protocol = port.protocol.clone().unwrap_or_else(|| "TCP".to_string());
+        let proto = protocol.to_lowercase();
+        let url = format!("{}://{}:{}", proto, host, port.port);
+        if i == 0 {
+            result.insert(format!("{}_PORT", name), url.clone());
+        }
+        let prefix = format!("{}_PORT_{}_{}", name, port.port, protocol.to_uppercase());
+        result.insert(prefix.clone(), url);
+        result.insert(format!("{}_PROTO", prefix), proto);
+        result.insert(format!("{}_PORT", prefix), port.port.to_string());
+        result.insert(format!("{}_ADDR", prefix), ip.to_string());
+    }
+    result
+}
+
+fn is_service_ip_set(service: &Service) -> bool {
+    match service.spec.as_ref().and_then(|s| s.cluster_ip.as_deref()) {
+        Some(ip) => !ip.is_empty() && ip != "None",
+        None => false,
+    }
+}
+
+fn make_env_variable_name(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Computes the final container environment. `envFrom` sources come first,
+/// variables declared on the container override them and win over service
+/// variables, and `$(VAR)` references in values are expanded against
+/// everything defined before them plus the service variables. A ConfigMap,
+/// Secret or key that is missing and not optional is an error, which fails
+/// the container with CreateContainerConfigError.
+pub async fn make_environment_variables(
+    pod: &Pod,
+    pod_ips: &[String],
+    container: &Container,
+    service_env: &BTreeMap<String, String>,
+) -> anyhow::Result<Vec<cri::KeyValue>> {
+    let mut sources = Sources::new(pod.namespace().unwrap_or_default());
+    resolve_environment(pod, pod_ips, container, service_env, &mut sources).await
+}
+
+async fn resolve_environment(
+    pod: &Pod,
+    pod_ips: &[String],
+    container: &Container,
+    service_env: &BTreeMap<String, String>,
+    sources: &mut Sources,
+) -> anyhow::Result<Vec<cri::KeyValue>> {
+    let mut env: Vec<(String, String)> = vec![];
+    let mut defined: BTreeMap<String, String> = BTreeMap::new();
+    for source in container.env_from.clone().unwrap_or_default() {
+        let data = if let Some(config_map) = &source.config_map_ref {
+            sources.config_map(config_map.name.as_deref().unwrap_or_default(), config_map.optional.unwrap_or(false)).await?
+        } else if let Some(secret) = &source.secret_ref {
+            sources.secret(secret.name.as_deref().unwrap_or_default(), secret.optional.unwrap_or(false)).await?
+        } else {
+            None
+        };
+        let prefix = source.prefix.unwrap_or_default();
+        for (key, value) in data.unwrap_or_default() {
+            let name = format!("{}{}", prefix, key);
+            if !is_env_var_name(&name) {
+                warn!("Skipping invalid environment variable name {} from envFrom in {}", name, pod.name_any());
+                continue;
+            }
+            define(&mut env, &mut defined, name, value);
+        }
+    }
+    for var in container.env.clone().unwrap_or_default() {
+        let value = match (&var.value, &var.value_from) {
+            (Some(value), _) => expand(value, |key| {
+                defined.get(key).or_else(|| service_env.get(key)).cloned()
+            }),
+            (None, Some(source)) => match value_from(pod, pod_ips, container, source, sources).await? {
+                Some(value) => value,
+                // An optional reference that does not resolve leaves the variable unset.
+                None => continue,
+            },
+            (None, None) => String::new(),
+        };
+        define(&mut env, &mut defined, var.name, value);
+    }
+    for (key, value) in service_env {
+        if !defined.contains_key(key) {
+            env.push((key.clone(), value.clone()));
+        }
+    }
+    Ok(env.into_iter().map(|(key, value)| cri::KeyValue { key, value }).collect())
+}
+
+/// Sets a variable, keeping the position of an earlier definition.
+fn define(env: &mut Vec<(String, String)>, defined: &mut BTreeMap<String, String>, name: String, value: String) {
+    match env.iter_mut().find(|(k, _)| *k == name) {
+        Some(entry) => entry.1 = value.clone(),
+        None => env.push((name.clone(), value.clone())),
+    }
+    defined.insert(name, value);
+}
+
+/// ConfigMaps and Secrets of the pod's namespace, each fetched at most once.
+/// `None` marks one that does not exist.
+struct Sources {
+    namespace: String,
+    config_maps: BTreeMap<String, Option<BTreeMap<String, String>>>,
+    secrets: BTreeMap<String, Option<BTreeMap<String, String>>>,
+}
+
+impl Sources {
+    fn new(namespace: String) -> Self {
+        Sources { namespace, config_maps: BTreeMap::new(), secrets: BTreeMap::new() }
+    }
+
+    /// The ConfigMap's data; `None` if it is missing and `optional`.
+    async fn config_map(&mut self, name: &str, optional: bool) -> anyhow::Result<Option<BTreeMap<String, String>>> {
+        if !self.config_maps.contains_key(name) {
+            let api: Api<ConfigMap> = Api::namespaced(client::client().await?, &self.namespace);
+            let data = api.get_opt(name).await?.map(|config_map| config_map.data.unwrap_or_default());
+            self.config_maps.insert(name.to_string(), data);
+        }
+        match &self.config_maps[name] {
+            Some(data) => Ok(Some(data.clone())),
+            None if optional => Ok(None),
+            None => anyhow::bail!("configmap \"{}\" not found", name),
+        }
+    }
+
+    /// The Secret's data; `None` if it is missing and `optional`.
+    async fn secret(&mut self, name: &str, optional: bool) -> anyhow::Result<Option<BTreeMap<String, String>>> {
+        if !self.secrets.contains_key(name) {
+            let api: Api<Secret> = Api::namespaced(client::client().await?, &self.namespace);
+            let data = api.get_opt(name).await?.map(|secret| {
+                secret
+                    .data
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(key, value)| (key, String::from_utf8_lossy(&value.0).into_owned()))
+                    .collect()
+            });
+            self.secrets.insert(name.to_string(), data);
+        }
+        match &self.secrets[name] {
+            Some(data) => Ok(Some(data.clone())),
+            None if optional => Ok(None),
+            None => anyhow::bail!("secret \"{}\" not found", name),
+        }
+    }
+}
+
+/// Resolves a `valueFrom` source; `None` for an optional reference that
+/// does not resolve.
+async fn value_from(
+    pod: &Pod,
+    pod_ips: &[String],
+    container: &Container,
+    source: &EnvVarSource,
+    sources: &mut Sources,
+) -> anyhow::Result<Option<String>> {
+    if let Some(field) = &source.field_ref {
+        return pod_field_value(pod, pod_ips, &field.field_path).map(Some);
+    }
+    if let Some(selector) = &source.resource_field_ref {
+        return resource_field_value(pod, container, selector).map(Some);
+    }
+    let (kind, name, key, optional, data) = if let Some(selector) = &source.config_map_key_ref {
+        let (name, optional) = (selector.name.as_deref().unwrap_or_default(), selector.optional.unwrap_or(false));
+        ("ConfigMap", name, &selector.key, optional, sources.config_map(name, optional).await?)
+    } else if let Some(selector) = &source.secret_key_ref {
+        let (name, optional) = (selector.name.as_deref().unwrap_or_default(), selector.optional.unwrap_or(false));
+        ("Secret", name, &selector.key, optional, sources.secret(name, optional).await?)
+    } else {
+        anyhow::bail!("valueFrom has no source set");
+    };
+    match data.and_then(|data| data.get(key).cloned()) {
+        Some(value) => Ok(Some(value)),
+        None if optional => Ok(None),
+        None => anyhow::bail!("couldn't find key {} in {} {}/{}", key, kind, sources.namespace, name),
+    }
+}
+
+/// `limits.<resource>` or `requests.<resource>` of the selected container in
+/// units of the divisor, rounded up. Unset limits default to the node's
+/// allocatable amount, as upstream.
+fn resource_field_value(pod: &Pod, container: &Container, selector: &ResourceFieldSelector) -> anyhow::Result<String> {
+    let container = match selector.container_name.as_deref().filter(|name| !name.is_empty()) {
+        Some(name) => pod
+            .spec
+            .as_ref()
+            .and_then(|s| s.containers.iter().find(|c| c.name == name))
+            .ok_or_else(|| anyhow::anyhow!("container {} not found for resourceFieldRef", name))?,
+        None => container,
+    };
+    let resources = container.resources.as_ref();
+    let (values, resource) = match selector.resource.split_once('.') {
+        Some(("limits", resource)) => (resources.and_then(|r| r.limits.as_ref()), resource),
+        Some(("requests", resource)) => (resources.and_then(|r| r.requests.as_ref()), resource),
+        _ => anyhow::bail!("unsupported container resource {}", selector.resource),
+    };
+    let milli = resource == "cpu";
+    let amount = match values.and_then(|v| v.get(resource)) {
+        Some(q) if milli => parse_quantity(q)?.milli_value(),
+        Some(q) => parse_quantity(q)?.value(),
+        None if selector.resource.starts_with("limits.") => {
+            let allocatable = capacity::allocatable(&capacity::capacity())?;
+            allocatable.get(resource).copied().unwrap_or_default()
+        }
+        None => 0,
+    };
+    let divisor = match &selector.divisor {
+        Some(divisor) => parse_quantity(divisor)?,
+        None => quantity::parse("1")?,
+    };
+    let divisor = if milli { divisor.milli_value() } else { divisor.value() };
+    if divisor <= 0 {
+        anyhow::bail!("invalid divisor for {}", selector.resource);
+    }
+    Ok((amount as f64 / divisor as f64).ceil().to_string())
+}
+
+/// A C identifier-like name, also allowing `-` and `.` as the API server does.
+fn is_env_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// The downward API fields a variable may reference; unset fields are empty.
+fn pod_field_value(pod: &Pod, pod_ips: &[String], path: &str) -> anyhow::Result<String> {
+    let spec = pod.spec.as_ref();
+    let value = match path {
+        "metadata.name" => pod.metadata.name.clone(),
+        "metadata.namespace" => pod.metadata.namespace.clone(),
+        "metadata.uid" => pod.metadata.uid.clone(),
+        "spec.nodeName" => spec.and_then(|s| s.node_name.clone()),
+        "spec.serviceAccountName" => spec.and_then(|s| s.service_account_name.clone()),
+        "status.hostIP" => node_ip().map(|ip| ip.to_string()),
+        "status.podIP" => pod_ips.first().cloned(),
+        "status.podIPs" => Some(pod_ips.join(",")),
+        _ => {
+            let (map, key) = if let Some(key) = path.strip_prefix("metadata.labels['") {
+                (pod.metadata.labels.as_ref(), key)
+            } else if let Some(key) = path.strip_prefix("metadata.annotations['") {
+                (pod.metadata.annotations.as_ref(), key)
+            } else {
+                anyhow::bail!("unsupported fieldPath {}", path);
+            };
+            map.and_then(|m| m.get(key.trim_end_matches("']")).cloned())
+        }
+    };
+    Ok(value.unwrap_or_default())
+}
+
+/// Expands `$(VAR)` references the way the API server documents it: `$$`
+/// escapes a reference and unresolvable references are left untouched.
+fn expand(input: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if let Some(stripped) = after.strip_prefix('$') {
+            out.push('$');
+            rest = stripped;
+        } else if let Some(body) = after.strip_prefix('(') {
+            match body.find(')') {
+                Some(end) => {
+                    let name = &body[..end];
+                    match lookup(name) {
+                        Some(value) => out.push_str(&value),
+                        None => out.push_str(&format!("$({})", name)),
+                    }
+                    rest = &body[end + 1..];
+                }
+                None => {
+                    out.push_str("$(");
+                    rest = body;
+                }
+            }
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{
+        ConfigMapEnvSource, ConfigMapKeySelector, EnvFromSource, EnvVar, ObjectFieldSelector, PodSpec,
+        SecretEnvSource, SecretKeySelector, ServicePort, ServiceSpec,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn lookup(key: &str) -> Option<String> {
+        match key {
+            "A" => Some("a".to_string()),
+            "B" => Some("$(A)".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_references() {
+        assert_eq!(expand("x-$(A)-y", lookup), "x-a-y");
+        assert_eq!(expand("$(A)$(A)", lookup), "aa");
+        // Expanded values are not expanded again.
+        assert_eq!(expand("$(B)", lookup), "$(A)");
+    }
+
+    #[test]
+    fn expand_escapes_and_leaves_unresolved_references() {
+        assert_eq!(expand("$$(A)", lookup), "$(A)");
+        assert_eq!(expand("$$$(A)", lookup), "$a");
+        assert_eq!(expand("$(MISSING)", lookup), "$(MISSING)");
+        assert_eq!(expand("$(A", lookup), "$(A");
+        assert_eq!(expand("cost: $5", lookup), "cost: $5");
+    }
+
+    fn service(namespace: &str, name: &str, ip: &str, ports: &[(&str, i32, &str)]) -> Service {
+        Service {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                cluster_ip: Some(ip.to_string()),
+                ports: Some(
+                    ports
+                        .iter()
+                        .map(|(name, port, protocol)| ServicePort {
+                            name: Some(name.to_string()).filter(|n| !n.is_empty()),
+                            port: *port,
+                            protocol: Some(protocol.to_string()),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn generates_service_variables() {
+        let svc = service("ns", "my-svc", "10.0.0.1", &[("http", 80, "TCP"), ("dns", 53, "UDP")]);
+        let env = from_services(&[&svc]);
+        assert_eq!(env["MY_SVC_SERVICE_HOST"], "10.0.0.1");
+        assert_eq!(env["MY_SVC_SERVICE_PORT"], "80");
+        assert_eq!(env["MY_SVC_SERVICE_PORT_HTTP"], "80");
+        assert_eq!(env["MY_SVC_SERVICE_PORT_DNS"], "53");
+        assert_eq!(env["MY_SVC_PORT"], "tcp://10.0.0.1:80");
+        assert_eq!(env["MY_SVC_PORT_53_UDP"], "udp://10.0.0.1:53");
+        assert_eq!(env["MY_SVC_PORT_53_UDP_PROTO"], "udp");
+        assert_eq!(env["MY_SVC_PORT_53_UDP_PORT"], "53");
+        assert_eq!(env["MY_SVC_PORT_53_UDP_ADDR"], "10.0.0.1");
+
+        let v6 = service("ns", "v6", "fd00::1", &[("", 443, "TCP")]);
+        assert_eq!(from_services(&[&v6])["V6_PORT"], "tcp://[fd00::1]:443");
+    }
+
+    #[test]
+    fn selects_services_for_namespace() {
+        let services = vec![
+            service("default", "kubernetes", "10.0.0.1", &[("https", 443, "TCP")]),
+            service("ns", "web", "10.0.0.2", &[("", 80, "TCP")]),
+            service("other", "db", "10.0.0.3", &[("", 5432, "TCP")]),
+            service("ns", "headless", "None", &[("", 80, "TCP")]),
+        ];
+        let env = service_env_map(&services, "ns", true);
+        assert_eq!(env["KUBERNETES_SERVICE_HOST"], "10.0.0.1");
+        assert_eq!(env["WEB_SERVICE_HOST"], "10.0.0.2");
+        assert!(!env.contains_key("DB_SERVICE_HOST"));
+        assert!(!env.contains_key("HEADLESS_SERVICE_HOST"));
+
+        let env = service_env_map(&services, "ns", false);
+        assert!(env.contains_key("KUBERNETES_SERVICE_HOST"));
+        assert!(!env.contains_key("WEB_SERVICE_HOST"));
+
+        // A "kubernetes" service in the pod's namespace shadows the master service.
+        let mut shadowed = services.clone();
+        shadowed.push(service("ns", "kubernetes", "10.0.0.9", &[("", 443, "TCP")]));
+        assert_eq!(service_env_map(&shadowed, "ns", true)["KUBERNETES_SERVICE_HOST"], "10.0.0.9");
+    }
+
+    fn pod(container: Container) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("web-0".to_string()),
+                namespace: Some("ns".to_string()),
+                labels: Some(BTreeMap::from([("app".to_string(), "web".to_string())])),
+                ..Default::default()
+            },
+            spec: Some(PodSpec { containers: vec![container], ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    fn var(name: &str, value: &str) -> EnvVar {
+        EnvVar { name: name.to_string(), value: Some(value.to_string()), value_from: None }
+    }
+
+    fn var_from(name: &str, source: EnvVarSource) -> EnvVar {
+        EnvVar { name: name.to_string(), value: None, value_from: Some(source) }
+    }
+
+    fn sources() -> Sources {
+        let mut sources = Sources::new("ns".to_string());
+        let data = |pairs: &[(&str, &str)]| {
+            Some(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>())
+        };
+        sources.config_maps.insert("config".to_string(), data(&[("MODE", "cm"), ("LEVEL", "info"), ("1BAD", "x")]));
+        sources.config_maps.insert("missing".to_string(), None);
+        sources.secrets.insert("creds".to_string(), data(&[("MODE", "secret"), ("password", "hunter2")]));
+        sources
+    }
+
+    async fn resolve(container: Container, service_env: &[(&str, &str)]) -> anyhow::Result<Vec<(String, String)>> {
+        let pod = pod(container.clone());
+        let service_env = service_env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let env = resolve_environment(&pod, &["10.1.0.5".to_string()], &container, &service_env, &mut sources()).await?;
+        Ok(env.into_iter().map(|kv| (kv.key, kv.value)).collect())
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn expands_only_earlier_and_service_variables() {
+        let container = Container {
+            name: "app".to_string(),
+            env: Some(vec![
+                var("FIRST", "$(SECOND)-$(SVC_HOST)"),
+                var("SECOND", "two"),
+                var("THIRD", "$(SECOND)"),
+            ]),
+            ..Default::default()
+        };
+        let env = resolve(container, &[("SVC_HOST", "10.0.0.1")]).await.unwrap();
+        assert_eq!(
+            env,
+            pairs(&[("FIRST", "$(SECOND)-10.0.0.1"), ("SECOND", "two"), ("THIRD", "two"), ("SVC_HOST", "10.0.0.1")])
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_env_from_and_value_from_with_precedence() {
+        let container = Container {
+            name: "app".to_string(),
+            env_from: Some(vec![
+                EnvFromSource {
+                    config_map_ref: Some(ConfigMapEnvSource { name: Some("config".to_string()), optional: None }),
+                    ..Default::default()
+                },
+                // Later sources override earlier ones.
+                EnvFromSource {
+                    secret_ref: Some(SecretEnvSource { name: Some("creds".to_string()), optional: None }),
+                    ..Default::default()
+                },
+                EnvFromSource {
+                    config_map_ref: Some(ConfigMapEnvSource {
+                        name: Some("missing".to_string()),
+                        optional: Some(true),
+                    }),
+                    ..Default::default()
+                },
+            ]),
+            env: Some(vec![
+                // env overrides envFrom but keeps its position.
+                var("LEVEL", "debug"),
+                var_from(
+                    "PASSWORD",
+                    EnvVarSource {
+                        secret_key_ref: Some(SecretKeySelector {
+                            name: Some("creds".to_string()),
+                            key: "password".to_string(),
+                            optional: None,
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                var_from(
+                    "APP",
+                    EnvVarSource {
+                        field_ref: Some(ObjectFieldSelector {
+                            field_path: "metadata.labels['app']".to_string(),
+                            api_version: None,
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                var_from(
+                    "POD_IP",
+                    EnvVarSource {
+                        field_ref: Some(ObjectFieldSelector {
+                            field_path: "status.podIP".to_string(),
+                            api_version: None,
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                var_from(
+                    "OPTIONAL",
+                    EnvVarSource {
+                        config_map_key_ref: Some(ConfigMapKeySelector {
+                            name: Some("config".to_string()),
+                            key: "absent".to_string(),
+                            optional: Some(true),
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                // Container variables win over service variables.
+                var("SVC_HOST", "mine"),
+            ]),
+            ..Default::default()
+        };
+        let env = resolve(container, &[("SVC_HOST", "10.0.0.1"), ("SVC_PORT", "80")]).await.unwrap();
+        assert_eq!(
+            env,
+            pairs(&[
+                ("LEVEL", "debug"),
+                ("MODE", "secret"),
+                ("password", "hunter2"),
+                ("PASSWORD", "hunter2"),
+                ("APP", "web"),
+                ("POD_IP", "10.1.0.5"),
+                ("SVC_HOST", "mine"),
+                ("SVC_PORT", "80"),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_required_references() {
+        let key_ref = |name: &str, key: &str| EnvVarSource {
+            config_map_key_ref: Some(ConfigMapKeySelector {
+                name: Some(name.to_string()),
+                key: key.to_string(),
+                optional: None,
+            }),
+            ..Default::default()
+        };
+        for source in [key_ref("config", "absent"), key_ref("missing", "MODE")] {
+            let container =
+                Container { name: "app".to_string(), env: Some(vec![var_from("X", source)]), ..Default::default() };
+            assert!(resolve(container, &[]).await.is_err());
+        }
+        let container = Container {
+            name: "app".to_string(),
+            env_from: Some(vec![EnvFromSource {
+                config_map_ref: Some(ConfigMapEnvSource { name: Some("missing".to_string()), optional: None }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert!(resolve(container, &[]).await.is_err());
+    }
+
+    #[test]
+    fn validates_variable_names() {
+        assert!(is_env_var_name("MY_VAR"));
+        assert!(is_env_var_name("my.var-1"));
+        assert!(!is_env_var_name("1VAR"));
+        assert!(!is_env_var_name("MY VAR"));
+        assert!(!is_env_var_name(""));
+    }
+}
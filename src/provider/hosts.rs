@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::kubelet::config::config;
+use crate::provider::{cri, namespaces};
+
+const HOSTNAME_MAX_LEN: usize = 63;
+const FQDN_MAX_LEN: usize = 64;
+const ETC_HOSTS_PATH: &str = "/etc/hosts";
+const ETC_HOSTS_FILE: &str = "etc-hosts";
+
+/// The pod's hostname and, when it has a subdomain, its DNS domain.
+pub fn hostname_and_domain(pod: &Pod) -> (String, Option<String>) {
+    let spec = pod.spec.as_ref();
+    let name = pod.metadata.name.clone().unwrap_or_default();
+    let hostname = spec
+        .and_then(|s| s.hostname.clone())
+        .filter(|h| !h.is_empty())
+        .unwrap_or(name);
+    let hostname = truncate_hostname(&hostname);
+    let domain = spec
+        .and_then(|s| s.subdomain.as_deref())
+        .filter(|s| !s.is_empty())
+        .map(|subdomain| {
+            format!(
+                "{}.{}.svc.{}",
+                subdomain,
+                pod.metadata.namespace.clone().unwrap_or_default(),
+                config().cluster_domain
+            )
+        });
+    (hostname, domain)
+}
+
+/// Hostnames are DNS labels, so long pod names are cut to 63 characters
+/// without leaving a trailing `-` or `.`.
+fn truncate_hostname(hostname: &str) -> String {
+    if hostname.len() <= HOSTNAME_MAX_LEN {
+        return hostname.to_string();
+    }
+    hostname[..HOSTNAME_MAX_LEN].trim_end_matches(['-', '.']).to_string()
+}
+
+/// The hostname handed to the sandbox: empty on the host network, the FQDN
+/// when `setHostnameAsFQDN` is set, otherwise the short hostname.
+pub fn sandbox_hostname(pod: &Pod) -> anyhow::Result<String> {
+    if namespaces::host_network(pod) {
+        return Ok(String::new());
+    }
+    let (hostname, domain) = hostname_and_domain(pod);
+    let as_fqdn = pod.spec.as_ref().and_then(|s| s.set_hostname_as_fqdn).unwrap_or(false);
+    match domain {
+        Some(domain) if as_fqdn => {
+            let fqdn = format!("{}.{}", hostname, domain);
+            if fqdn.len() > FQDN_MAX_LEN {
+                anyhow::bail!(
+                    "failed to construct FQDN from pod hostname and cluster domain, FQDN {} is too long ({} characters is the max, {} characters requested)",
+                    fqdn,
+                    FQDN_MAX_LEN,
+                    fqdn.len()
+                );
+            }
+            Ok(fqdn)
+        }
+        _ => Ok(hostname),
+    }
+}
+
+fn etc_hosts_file(pod_uid: &str) -> PathBuf {
+    config().pod_dir(pod_uid).join(ETC_HOSTS_FILE)
+}
+
+fn host_aliases_content(pod: &Pod) -> String {
+    let aliases = pod.spec.as_ref().and_then(|s| s.host_aliases.clone()).unwrap_or_default();
+    if aliases.is_empty() {
+        return String::new();
+    }
+    let mut content = String::from("\n# Entries added by HostAliases.\n");
+    for alias in aliases {
+        let hostnames = alias.hostnames.unwrap_or_default().join("\t");
+        content.push_str(&format!("{}\t{}\n", alias.ip.unwrap_or_default(), hostnames));
+    }
+    content
+}
+
+fn managed_hosts_content(pod: &Pod, pod_ips: &[String]) -> String {
+    let (hostname, domain) = hostname_and_domain(pod);
+    let mut content = String::from(
+        "# Kubernetes-managed hosts file.\n\
+         127.0.0.1\tlocalhost\n\
+         ::1\tlocalhost ip6-localhost ip6-loopback\n\
+         fe00::0\tip6-localnet\n\
+         fe00::0\tip6-mcastprefix\n\
+         fe00::1\tip6-allnodes\n\
+         fe00::2\tip6-allrouters\n",
+    );
+    for ip in pod_ips {
+        match &domain {
+            Some(domain) => content.push_str(&format!("{}\t{}.{}\t{}\n", ip, hostname, domain, hostname)),
+            None => content.push_str(&format!("{}\t{}\n", ip, hostname)),
+        }
+    }
+    content + &host_aliases_content(pod)
+}
+
+async fn host_network_hosts_content(pod: &Pod) -> anyhow::Result<String> {
+    let node_hosts = tokio::fs::read_to_string(ETC_HOSTS_PATH).await?;
+    Ok(format!(
+        "# Kubernetes-managed hosts file (host network).\n{}{}",
+        node_hosts,
+        host_aliases_content(pod)
+    ))
+}
+
+/// Writes `<podDir>/etc-hosts` once the pod IPs are known.
+pub async fn ensure_hosts_file(pod: &Pod, pod_ips: &[String]) -> anyhow::Result<()> {
+    let content = if namespaces::host_network(pod) {
+        host_network_hosts_content(pod).await?
+    } else {
+        managed_hosts_content(pod, pod_ips)
+    };
+    let path = etc_hosts_file(&pod.metadata.uid.clone().unwrap_or_default());
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    tokio::fs::write(&path, content).await?;
+    Ok(())
+}
+
+/// Mount for the managed hosts file, if it has been written.
+pub fn etc_hosts_mount(pod: &Pod) -> Option<cri::Mount> {
+    let path = etc_hosts_file(&pod.metadata.uid.clone().unwrap_or_default());
+    if !path.exists() {
+        return None;
+    }
+    Some(cri::Mount {
+        container_path: ETC_HOSTS_PATH.to_string(),
+        host_path: path.to_string_lossy().into_owned(),
+        readonly: false,
+        selinux_relabel: true,
+        propagation: cri::MountPropagation::PropagationPrivate as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{HostAlias, PodSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    const HEADER: &str = "# Kubernetes-managed hosts file.\n\
+                          127.0.0.1\tlocalhost\n\
+                          ::1\tlocalhost ip6-localhost ip6-loopback\n\
+                          fe00::0\tip6-localnet\n\
+                          fe00::0\tip6-mcastprefix\n\
+                          fe00::1\tip6-allnodes\n\
+                          fe00::2\tip6-allrouters\n";
+
+    fn pod(hostname: Option<&str>, subdomain: Option<&str>, aliases: Vec<HostAlias>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("web-0".to_string()),
+                namespace: Some("ns".to_string()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                hostname: hostname.map(str::to_string),
+                subdomain: subdomain.map(str::to_string),
+                host_aliases: Some(aliases).filter(|a| !a.is_empty()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn ips() -> Vec<String> {
+        vec!["10.1.0.5".to_string(), "fd00::5".to_string()]
+    }
+
+    #[test]
+    fn renders_pod_ips_with_hostname() {
+        let content = managed_hosts_content(&pod(None, None, vec![]), &ips());
+        assert_eq!(content, format!("{}10.1.0.5\tweb-0\nfd00::5\tweb-0\n", HEADER));
+
+        let content = managed_hosts_content(&pod(Some("db"), None, vec![]), &ips()[..1]);
+        assert_eq!(content, format!("{}10.1.0.5\tdb\n", HEADER));
+    }
+
+    #[test]
+    fn renders_fqdn_for_subdomain() {
+        let content = managed_hosts_content(&pod(Some("db"), Some("backend"), vec![]), &ips());
+        assert_eq!(
+            content,
+            format!(
+                "{}10.1.0.5\tdb.backend.ns.svc.cluster.local\tdb\nfd00::5\tdb.backend.ns.svc.cluster.local\tdb\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn renders_host_aliases() {
+        let aliases = vec![
+            HostAlias { ip: Some("127.0.0.1".to_string()), hostnames: Some(vec!["foo.local".to_string()]) },
+            HostAlias {
+                ip: Some("10.1.2.3".to_string()),
+                hostnames: Some(vec!["foo.remote".to_string(), "bar.remote".to_string()]),
+            },
+        ];
+        let content = managed_hosts_content(&pod(None, None, aliases), &ips()[..1]);
+        assert_eq!(
+            content,
+            format!(
+                "{}10.1.0.5\tweb-0\n\n# Entries added by HostAliases.\n\
+                 127.0.0.1\tfoo.local\n\
+                 10.1.2.3\tfoo.remote\tbar.remote\n",
+                HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn truncates_long_hostnames() {
+        let name = format!("{}-{}", "a".repeat(62), "b".repeat(10));
+        assert_eq!(truncate_hostname(&name), "a".repeat(62));
+        assert_eq!(truncate_hostname("short"), "short");
+    }
+}
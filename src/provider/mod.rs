@@ -1,13 +1,43 @@
 use tonic::transport::channel::Channel;
+use tonic::transport::Endpoint;
 
+use crate::metrics::instrumented::Instrumented;
+
+use cri::image_service_client::ImageServiceClient;
 use cri::runtime_service_client::RuntimeServiceClient;
 
-mod cri;
+#[allow(clippy::doc_lazy_continuation, clippy::doc_overindented_list_items, clippy::enum_variant_names)]
+pub(crate) mod cri;
+pub mod admission;
+mod dns;
+mod envvars;
+mod hosts;
+mod namespaces;
 pub mod pod;
+pub(crate) mod pod_manager;
+mod ports;
+pub(crate) mod qos;
+pub(crate) mod quantity;
+mod resources;
+mod security;
+pub mod service;
+mod sysctl;
+mod termination;
+pub mod userns;
+
 
+/// Address of the CRI runtime; relative streaming URLs resolve against it.
+pub(crate) const RUNTIME_ENDPOINT: &str = "http://192.168.50.231:8989";
 
-async fn get_client() -> RuntimeServiceClient<Channel> {
-    let url = "http://192.168.50.231:8989";
-    RuntimeServiceClient::connect(url).await.expect("Could not create client.")
+async fn runtime_channel() -> Instrumented<Channel> {
+    let channel = Endpoint::from_static(RUNTIME_ENDPOINT).connect().await.expect("Could not create client.");
+    Instrumented::runtime(channel)
 }
 
+pub(crate) async fn get_client() -> RuntimeServiceClient<Instrumented<Channel>> {
+    RuntimeServiceClient::new(runtime_channel().await)
+}
+
+pub(crate) async fn get_image_client() -> ImageServiceClient<Instrumented<Channel>> {
+    ImageServiceClient::new(runtime_channel().await)
+}
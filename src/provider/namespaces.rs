@@ -0,0 +1,29 @@
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::provider::{cri, userns};
+use crate::provider::cri::NamespaceMode;
+
+pub fn host_network(pod: &Pod) -> bool {
+    pod.spec.as_ref().and_then(|s| s.host_network).unwrap_or(false)
+}
+
+/// Namespace modes shared by the sandbox and every container of the pod.
+/// The PID namespace is per container unless the pod shares it or uses the host's.
+pub fn namespaces_for_pod(pod: &Pod) -> cri::NamespaceOption {
+    let spec = pod.spec.clone().unwrap_or_default();
+    let node_or = |host: Option<bool>, default: NamespaceMode| {
+        if host.unwrap_or(false) { NamespaceMode::Node } else { default }
+    };
+    let pid_default = if spec.share_process_namespace.unwrap_or(false) {
+        NamespaceMode::Pod
+    } else {
+        NamespaceMode::Container
+    };
+    cri::NamespaceOption {
+        network: node_or(spec.host_network, NamespaceMode::Pod) as i32,
+        pid: node_or(spec.host_pid, pid_default) as i32,
+        ipc: node_or(spec.host_ipc, NamespaceMode::Pod) as i32,
+        userns_options: Some(userns::user_namespace_for_pod(pod)),
+        ..Default::default()
+    }
+}
@@ -1,25 +1,124 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use k8s_openapi::api::core::v1::Pod;
-use kube::{Api, Client};
-use kube::api::PatchParams;
+use chrono::Utc;
+use k8s_openapi::api::core::v1::{Container, Pod};
+use kube::{Api, ResourceExt};
+use kube::api::{DeleteParams, PatchParams, Preconditions};
 use tokio::time;
 use tracing::*;
 
-use crate::provider::{cri, get_client};
+use crate::kubelet::client;
+use crate::kubelet::config::config;
+use crate::kubelet::health;
+use crate::logs;
+use crate::metrics::kubelet as kubelet_metrics;
+use crate::nodemod::address::node_ip;
+use crate::provider::{admission, cri, dns, envvars, get_client, get_image_client, hosts, namespaces, pod_manager, ports, qos, resources, security, service, termination, userns};
 use crate::provider::cri::PodSandboxConfig;
 
+const POD_NAME_LABEL: &str = "io.kubernetes.pod.name";
+const POD_NAMESPACE_LABEL: &str = "io.kubernetes.pod.namespace";
+const POD_UID_LABEL: &str = "io.kubernetes.pod.uid";
+const CONTAINER_NAME_LABEL: &str = "io.kubernetes.container.name";
+
+fn pod_labels(o: &Pod) -> HashMap<String, String> {
+    HashMap::from([
+        (POD_NAME_LABEL.to_string(), o.name_any()),
+        (POD_NAMESPACE_LABEL.to_string(), o.namespace().unwrap_or_default()),
+        (POD_UID_LABEL.to_string(), o.uid().unwrap_or_default()),
+    ])
+}
+
 pub async fn run_pod(o: Pod) {
-    let (pod_sandbox_id, config) = create_sandbox(&o).await;
-    let container_id = create_container(&o, &pod_sandbox_id, &config).await;
+    let _worker = kubelet_metrics::pod_worker();
+    if let Err(rejection) = admission::admit(&o) {
+        admission::reject_pod(&o, &rejection).await;
+        return;
+    }
+    if let Err(e) = userns::allocate(&o) {
+        admission::reject_pod(&o, &admission::Rejection::new(userns::EXHAUSTED_REASON, e.to_string())).await;
+        return;
+    }
+    let (pod_sandbox_id, config) = match create_sandbox(&o).await {
+        Ok(sandbox) => sandbox,
+        Err(e) => {
+            error!("创建sandbox失败 {}: {}", o.name_any(), e);
+            abandon_pod(&o).await;
+            return;
+        }
+    };
+    let pod_ips: Vec<String> = pod_ip(&o, &pod_sandbox_id).await.into_iter().collect();
+    if let Err(e) = hosts::ensure_hosts_file(&o, &pod_ips).await {
+        error!("写入hosts文件失败 {}: {}", o.name_any(), e);
+    }
+    let container = &o.spec.as_ref().unwrap().containers[0];
+    let pull_duration = match ensure_image(&image_ref(container), &config).await {
+        Ok(duration) => duration,
+        Err(e) => {
+            error!("ErrImagePull {}: {}", o.name_any(), e);
+            abandon_pod(&o).await;
+            return;
+        }
+    };
+    let container_id = match create_container(&o, &pod_sandbox_id, &pod_ips, &config).await {
+        Ok(id) => id,
+        Err(e) => {
+            error!("CreateContainerConfigError {}: {}", o.name_any(), e);
+            abandon_pod(&o).await;
+            return;
+        }
+    };
     start_container(&container_id).await;
-    tokio::spawn(fetch_status_info());
+    record_pod_start(&o, pull_duration);
+    link_container_log(&o, &config, &container_id).await;
+}
+
+/// Undoes a pod start that failed after admission: removes any sandbox and
+/// gives back the pod's user namespace range and admission slot, so failed
+/// pods cannot exhaust the pool.
+async fn abandon_pod(o: &Pod) {
+    let uid = o.uid().unwrap_or_default();
+    if let Err(e) = remove_sandboxes(&uid).await {
+        warn!("Unable to remove the sandbox of failed pod {}: {}", o.name_any(), e);
+    }
+    userns::release(&uid);
+    pod_manager::remove(&uid);
 }
 
-pub async fn create_container(o: &Pod, pod_sandbox_id: &str, sandbox_config: &PodSandboxConfig) -> String {
-    let name = o.clone().spec.unwrap().clone().containers[0].clone().name;
-    let mut image = o.clone().spec.unwrap().clone().containers[0].clone().image.unwrap();
-    image = format!("docker.io/library/{}:latest", image);
+pub async fn create_container(
+    o: &Pod,
+    pod_sandbox_id: &str,
+    pod_ips: &[String],
+    sandbox_config: &PodSandboxConfig,
+) -> anyhow::Result<String> {
+    let container = o.clone().spec.unwrap().containers[0].clone();
+    let name = container.name.clone();
+    let image = image_ref(&container);
+
+    let namespace = o.metadata.namespace.clone().unwrap_or_else(|| "default".to_string());
+    let enable_service_links = o.spec.as_ref().and_then(|s| s.enable_service_links).unwrap_or(true);
+    let service_env = envvars::service_env_map(&service::services().await?, &namespace, enable_service_links);
+    let envs = envvars::make_environment_variables(o, pod_ips, &container, &service_env).await?;
+
+    let mut container_resources = resources::linux_container_resources(&container);
+    container_resources.oom_score_adj =
+        qos::container_oom_score_adjust(o, &container, qos::machine_memory_capacity());
+
+    let sc = security::effective_security_context(o, &container);
+    let image_user = image_user(&image).await?;
+    security::verify_run_as_non_root(o, &container, &sc, &image_user)?;
+    let security_context = security::linux_container_security_context(o, &container, &sc);
+
+    let log_path = logs::container_log_path(&name, 0);
+    logs::create_container_log_dir(Path::new(&sandbox_config.log_directory), &name).await?;
+
+    let mut mounts: Vec<cri::Mount> = hosts::etc_hosts_mount(o).into_iter().collect();
+    mounts.extend(termination::termination_message_mount(o, &container).await?);
+
+    let mut labels = pod_labels(o);
+    labels.insert(CONTAINER_NAME_LABEL.to_string(), container.name.clone());
 
     let container_config = cri::ContainerConfig {
         metadata: Option::from(cri::ContainerMetadata { name, attempt: 0 }),
@@ -27,19 +126,22 @@ pub async fn create_container(o: &Pod, pod_sandbox_id: &str, sandbox_config: &Po
         command: vec![],
         args: vec![],
         working_dir: "".to_string(),
-        envs: vec![],
-        mounts: vec![],
+        envs,
+        mounts,
         devices: vec![],
-        labels: Default::default(),
+        labels,
         annotations: Default::default(),
-        log_path: "/var/log/pods/container".to_string(),
+        log_path: log_path.to_string_lossy().into_owned(),
         stdin: false,
         stdin_once: false,
         tty: false,
-        linux: None,
+        linux: Some(cri::LinuxContainerConfig {
+            resources: Some(container_resources),
+            security_context: Some(security_context),
+        }),
         windows: None,
     };
-    let s = pod_sandbox_id.clone().to_owned();
+    let s = pod_sandbox_id.to_owned();
     let request = cri::CreateContainerRequest {
         pod_sandbox_id: s,
         config: Option::from(container_config),
@@ -47,11 +149,75 @@ pub async fn create_container(o: &Pod, pod_sandbox_id: &str, sandbox_config: &Po
     };
     let response = get_client().await
         .create_container(request)
-        .await
-        .expect("Request failed.");
+        .await?;
     info!("容器创建成功,id: {}", response.get_ref().clone().container_id);
     let container_id = response.get_ref().clone().container_id;
-    container_id
+    Ok(container_id)
+}
+
+/// Links `/var/log/containers/<pod>_<namespace>_<container>-<id>.log` to the
+/// container's log file.
+async fn link_container_log(o: &Pod, sandbox_config: &PodSandboxConfig, container_id: &str) {
+    let container_name = &o.spec.as_ref().unwrap().containers[0].name;
+    let target = Path::new(&sandbox_config.log_directory).join(logs::container_log_path(container_name, 0));
+    let link = logs::container_log_symlink(&o.name_any(), &o.namespace().unwrap_or_default(), container_name, container_id);
+    if let Err(e) = logs::create_log_symlink(&target, &link).await {
+        warn!("Unable to create log symlink {}: {}", link.display(), e);
+    }
+}
+
+fn image_ref(container: &Container) -> String {
+    format!("docker.io/library/{}:latest", container.image.clone().unwrap())
+}
+
+/// Pulls the image unless the runtime already has it, returning the time
+/// spent pulling.
+async fn ensure_image(image: &str, sandbox_config: &PodSandboxConfig) -> anyhow::Result<Duration> {
+    let spec = cri::ImageSpec { image: image.to_string(), annotations: Default::default() };
+    let status = get_image_client().await
+        .image_status(cri::ImageStatusRequest { image: Some(spec.clone()), verbose: false })
+        .await
+        .map_err(|e| anyhow::anyhow!("Unable to get image status for {}: {}", image, e))?;
+    if status.get_ref().image.is_some() {
+        return Ok(Duration::ZERO);
+    }
+    info!("拉取镜像 {}", image);
+    let start = Instant::now();
+    let request = cri::PullImageRequest {
+        image: Some(spec),
+        auth: None,
+        sandbox_config: Some(sandbox_config.clone()),
+    };
+    get_image_client().await
+        .pull_image(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Unable to pull image {}: {}", image, e))?;
+    let duration = start.elapsed();
+    kubelet_metrics::record_image_pull(duration);
+    Ok(duration)
+}
+
+/// Observes the pod start SLI: creation to containers started, minus image pulls.
+fn record_pod_start(o: &Pod, pull_duration: Duration) {
+    let Some(created) = o.metadata.creation_timestamp.as_ref() else {
+        return;
+    };
+    if let Ok(elapsed) = (Utc::now() - created.0).to_std() {
+        kubelet_metrics::record_pod_start(elapsed.saturating_sub(pull_duration));
+    }
+}
+
+async fn image_user(image: &str) -> anyhow::Result<security::ImageUser> {
+    let request = cri::ImageStatusRequest {
+        image: Some(cri::ImageSpec { image: image.to_string(), annotations: Default::default() }),
+        verbose: false,
+    };
+    let response = get_image_client().await
+        .image_status(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Unable to get image status for {}: {}", image, e))?;
+    security::ImageUser::from_image(response.get_ref().image.as_ref())
+        .map_err(|e| anyhow::anyhow!("Unable to determine the user of {}: {}", image, e))
 }
 
 
@@ -67,90 +233,331 @@ pub async fn start_container(container_id: &str) {
     info!("启动容器成功,id: {}",container_id);
 }
 
-pub async fn create_sandbox(o: &Pod) -> (String, PodSandboxConfig) {
+pub async fn create_sandbox(o: &Pod) -> anyhow::Result<(String, PodSandboxConfig)> {
     let name = o.clone().metadata.name.unwrap();
+    let uid = o.metadata.uid.clone().unwrap_or_default();
+    let namespace = o.metadata.namespace.clone().unwrap_or_else(|| "default".to_string());
+    let cgroup_parent = qos::pod_cgroup_parent(qos::pod_qos(o), &uid, config().cgroup_driver);
+    let log_directory = logs::pod_log_dir(&namespace, &name, &uid);
+    tokio::fs::create_dir_all(&log_directory).await?;
+    let sysctls = o
+        .spec
+        .as_ref()
+        .and_then(|s| s.security_context.as_ref())
+        .and_then(|sc| sc.sysctls.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| (s.name, s.value))
+        .collect();
     let config = cri::PodSandboxConfig {
         metadata: Option::from(cri::PodSandboxMetadata {
             name,
-            uid: "123".to_string(),
-            namespace: "default".to_string(),
+            uid,
+            namespace,
             attempt: 0,
         }),
-        hostname: "my_hostname".to_string(),
-        log_directory: "/var/log/pods/sandbox".to_string(),
-        dns_config: None,
-        port_mappings: vec![],
-        labels: Default::default(),
+        hostname: hosts::sandbox_hostname(o)?,
+        log_directory: log_directory.to_string_lossy().into_owned(),
+        dns_config: Some(dns::pod_dns_config(o)),
+        port_mappings: ports::port_mappings(o),
+        labels: pod_labels(o),
         annotations: Default::default(),
-        linux: None,
+        linux: Some(cri::LinuxPodSandboxConfig {
+            cgroup_parent,
+            security_context: Some(security::linux_sandbox_security_context(o)),
+            sysctls,
+            ..Default::default()
+        }),
         windows: None,
     };
 
     let request = cri::RunPodSandboxRequest { config: Option::from(config.clone()), runtime_handler: "".to_string() };
     let response = get_client().await
         .run_pod_sandbox(request)
-        .await.map_err(|e| error!("创建sandbox失败: {}", e)).unwrap();
+        .await?;
     let pod_sandbox_id = response.get_ref().clone().pod_sandbox_id;
     info!("沙箱容器id: {}", pod_sandbox_id);
-    (pod_sandbox_id, config)
+    Ok((pod_sandbox_id, config))
 }
 
-async fn fetch_status_info() {
-    loop {
-        let request = cri::ListPodSandboxRequest { filter: None };
-        let response_sandbox = get_client().await
-            .list_pod_sandbox(request)
-            .await
-            .expect("Request failed.");
+/// Tears down everything the kubelet created for a pod this node admitted,
+/// once, releasing its user namespace range, and if the pod is being
+/// gracefully deleted, confirms the deletion with the API server.
+pub async fn delete_pod(o: Pod) {
+    let uid = o.uid().unwrap_or_default();
+    if !pod_manager::start_termination(&uid) {
+        return;
+    }
+    let _worker = kubelet_metrics::pod_worker();
+    if let Err(e) = remove_sandboxes(&uid).await {
+        error!("删除sandbox失败 {}: {}", o.name_any(), e);
+        pod_manager::abort_termination(&uid);
+        return;
+    }
+    userns::release(&uid);
+    pod_manager::remove(&uid);
+    termination::forget(&uid);
+    let _ = tokio::fs::remove_dir_all(config().pod_dir(&uid)).await;
+    logs::remove_pod_logs(&o.namespace().unwrap_or_default(), &o.name_any(), &uid).await;
 
-        let request = cri::ListContainersRequest { filter: None };
-        let response = get_client().await
-            .list_containers(request)
-            .await
-            .expect("Request failed.");
-        for i in &response.get_ref().containers {
-            for j in &response_sandbox.get_ref().items {
-                if i.pod_sandbox_id == j.id {
-                    if i.state == 1 {
-                        info!("{} 空间下的pod: {:?} 状态: {}",
-                        j.metadata.clone().unwrap().namespace,j.metadata.clone().unwrap().name,"running");
-                        update_status(j.metadata.clone().unwrap().name, j.metadata.clone().unwrap().namespace).await;
-                    }
-                }
-            }
+    if o.metadata.deletion_timestamp.is_some() {
+        let client = client::client().await.unwrap();
+        let pod_client: Api<Pod> = Api::namespaced(client, &o.namespace().unwrap_or_default());
+        let params = DeleteParams {
+            grace_period_seconds: Some(0),
+            preconditions: Some(Preconditions { uid: Some(uid), resource_version: None }),
+            ..Default::default()
+        };
+        if let Err(e) = pod_client.delete(&o.name_any(), &params).await {
+            debug!("pod {} already deleted: {}", o.name_any(), e);
         }
-        time::sleep(Duration::from_secs(4)).await;
     }
 }
 
-async fn update_status(name: String, ns: String) {
-    let client = Client::try_default().await.unwrap();
-    let status_patch = serde_json::json!({
-    "status": {
-        "phase": "Running",
-        "qosClass": "BestEffort",
-        "containerStatuses": [
-            {
-                "containerID": "containerd://bbe48a0007e9d2eca406acc6ba75041ca6e163bbd4d7490ab3248a5eeb1797be",
-                "image": "docker.io/openebs/provisioner-localpv:3.3.0",
-                "imageID": "docker.io/openebs/provisioner-localpv@sha256:9944beedeb5ad33b1013d62da026c3ac31f29238b378335e40ded2dcfe2c56f4",
-                "name": "openebs-provisioner-hostpath",
-                "ready": true,
-                "restartCount": 1,
-                "started": true,
-                "state": {
-                    "running": {
-                        "startedAt": "2023-03-17T01:02:10Z"
-                    }
+async fn remove_sandboxes(uid: &str) -> anyhow::Result<()> {
+    let filter = cri::PodSandboxFilter {
+        label_selector: HashMap::from([(POD_UID_LABEL.to_string(), uid.to_string())]),
+        ..Default::default()
+    };
+    let request = cri::ListPodSandboxRequest { filter: Some(filter) };
+    let sandboxes = get_client().await.list_pod_sandbox(request).await?.into_inner().items;
+    for sandbox in sandboxes {
+        let id = sandbox.id;
+        get_client().await
+            .stop_pod_sandbox(cri::StopPodSandboxRequest { pod_sandbox_id: id.clone() })
+            .await?;
+        get_client().await
+            .remove_pod_sandbox(cri::RemovePodSandboxRequest { pod_sandbox_id: id.clone() })
+            .await?;
+        info!("删除sandbox成功,id: {}", id);
+    }
+    Ok(())
+}
+
+/// Relists the runtime's pods and containers and syncs their status to the
+/// API server, as the PLEG does.
+pub async fn fetch_status_info() {
+    loop {
+        let (sandboxes, containers) = match relist().await {
+            Ok(listed) => listed,
+            Err(e) => {
+                error!("relist失败: {}", e);
+                time::sleep(Duration::from_secs(4)).await;
+                continue;
+            }
+        };
+        health::relisted();
+        for i in &containers {
+            for j in &sandboxes {
+                if i.pod_sandbox_id == j.id && (i.state == 1 || i.state == 2) {
+                    info!("{} 空间下的pod: {:?} 状态: {}",
+                    j.metadata.clone().unwrap().namespace,j.metadata.clone().unwrap().name,"running");
+                    update_status(j.metadata.clone().unwrap().name, j.metadata.clone().unwrap().namespace, &j.id).await;
                 }
             }
-        ]
+        }
+        time::sleep(Duration::from_secs(4)).await;
     }
-});
+}
+
+async fn relist() -> Result<(Vec<cri::PodSandbox>, Vec<cri::Container>), tonic::Status> {
+    let request = cri::ListPodSandboxRequest { filter: None };
+    let sandboxes = get_client().await.list_pod_sandbox(request).await?.into_inner().items;
+    let request = cri::ListContainersRequest { filter: None };
+    let containers = get_client().await.list_containers(request).await?.into_inner().containers;
+    Ok((sandboxes, containers))
+}
+
+async fn update_status(name: String, ns: String, pod_sandbox_id: &str) {
+    let client = client::client().await.unwrap();
     let pod_client: Api<Pod> = Api::namespaced(client, &ns);
+    let pod = match pod_client.get(&name).await {
+        Ok(pod) => pod,
+        Err(e) => {
+            error!("获取pod失败 {}: {}", name, e);
+            return;
+        }
+    };
+    let qos_class = qos::pod_qos(&pod).as_str();
+    let host_ip = node_ip().map(|ip| ip.to_string()).unwrap_or_default();
+    let pod_ip = pod_ip(&pod, pod_sandbox_id).await;
+    let container_statuses = container_statuses(&pod, pod_sandbox_id).await;
+    let mut status_patch = serde_json::json!({
+        "status": {
+            "phase": pod_phase(&container_statuses),
+            "qosClass": qos_class,
+            "hostIP": host_ip,
+            "containerStatuses": container_statuses,
+        }
+    });
+    if let Some(ip) = pod_ip {
+        status_patch["status"]["podIP"] = serde_json::json!(ip);
+        status_patch["status"]["podIPs"] = serde_json::json!([{ "ip": ip }]);
+    }
     pod_client.patch_status(
         &name,
         &PatchParams::default(),
         &kube::api::Patch::Strategic(status_patch),
     ).await.expect("TODO: panic message");
-}
\ No newline at end of file
+}
+
+/// Statuses of the pod's containers as reported by the runtime.
+async fn container_statuses(pod: &Pod, pod_sandbox_id: &str) -> Vec<serde_json::Value> {
+    let filter = cri::ContainerFilter { pod_sandbox_id: pod_sandbox_id.to_string(), ..Default::default() };
+    let containers = match get_client().await
+        .list_containers(cri::ListContainersRequest { filter: Some(filter) })
+        .await
+    {
+        Ok(response) => response.into_inner().containers,
+        Err(e) => {
+            error!("获取容器列表失败: {}", e);
+            return vec![];
+        }
+    };
+    let runtime = match get_client().await.version(cri::VersionRequest::default()).await {
+        Ok(response) => response.into_inner().runtime_name,
+        Err(_) => "containerd".to_string(),
+    };
+    let message_limit = termination::message_limit(containers.len());
+    let mut statuses = vec![];
+    for c in containers {
+        let request = cri::ContainerStatusRequest { container_id: c.id.clone(), verbose: false };
+        let Some(status) = get_client().await
+            .container_status(request)
+            .await
+            .ok()
+            .and_then(|response| response.into_inner().status) else {
+            continue;
+        };
+        let name = status.metadata.as_ref().map(|m| m.name.clone()).unwrap_or_default();
+        let Some(container) = pod.spec.as_ref().and_then(|s| s.containers.iter().find(|c| c.name == name)) else {
+            continue;
+        };
+        let container_id = format!("{}://{}", runtime, status.id);
+        let state = match cri::ContainerState::from_i32(status.state) {
+            Some(cri::ContainerState::ContainerRunning) => serde_json::json!({
+                "running": { "startedAt": timestamp(status.started_at) }
+            }),
+            Some(cri::ContainerState::ContainerExited) => {
+                let reason = match status.reason.as_str() {
+                    "" if status.exit_code == 0 => "Completed",
+                    "" => "Error",
+                    reason => reason,
+                };
+                serde_json::json!({
+                    "terminated": {
+                        "exitCode": status.exit_code,
+                        "reason": reason,
+                        "message": termination::termination_message(pod, container, &status, message_limit).await,
+                        "startedAt": timestamp(status.started_at),
+                        "finishedAt": timestamp(status.finished_at),
+                        "containerID": container_id,
+                    }
+                })
+            }
+            _ => serde_json::json!({ "waiting": { "reason": "ContainerCreating" } }),
+        };
+        let running = status.state == cri::ContainerState::ContainerRunning as i32;
+        statuses.push(serde_json::json!({
+            "name": name,
+            "containerID": container_id,
+            "image": status.image.as_ref().map(|i| i.image.clone()).unwrap_or_default(),
+            "imageID": status.image_ref,
+            "restartCount": status.metadata.as_ref().map(|m| m.attempt).unwrap_or_default(),
+            "ready": running,
+            "started": running,
+            "state": state,
+        }));
+    }
+    statuses
+}
+
+/// Running while any container runs; once all have exited, Succeeded if
+/// they all exited cleanly and Failed otherwise.
+fn pod_phase(container_statuses: &[serde_json::Value]) -> &'static str {
+    if container_statuses.iter().any(|s| s["state"]["running"].is_object()) {
+        return "Running";
+    }
+    let exit_codes: Vec<i64> = container_statuses
+        .iter()
+        .filter_map(|s| s["state"]["terminated"]["exitCode"].as_i64())
+        .collect();
+    if exit_codes.is_empty() || exit_codes.len() < container_statuses.len() {
+        return "Pending";
+    }
+    if exit_codes.iter().all(|code| *code == 0) {
+        "Succeeded"
+    } else {
+        "Failed"
+    }
+}
+
+fn timestamp(nanos: i64) -> String {
+    chrono::DateTime::from_timestamp_nanos(nanos).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// The runtime's record of a pod's container: the latest instance, or with
+/// `previous` the one before it.
+pub async fn find_container(
+    namespace: &str,
+    pod_name: &str,
+    container_name: &str,
+    previous: bool,
+) -> anyhow::Result<Option<cri::ContainerStatus>> {
+    let filter = cri::ContainerFilter {
+        label_selector: HashMap::from([
+            (POD_NAMESPACE_LABEL.to_string(), namespace.to_string()),
+            (POD_NAME_LABEL.to_string(), pod_name.to_string()),
+            (CONTAINER_NAME_LABEL.to_string(), container_name.to_string()),
+        ]),
+        ..Default::default()
+    };
+    let mut containers = get_client().await
+        .list_containers(cri::ListContainersRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .containers;
+    containers.sort_by_key(|c| std::cmp::Reverse(c.created_at));
+    let Some(container) = containers.get(usize::from(previous)) else {
+        return Ok(None);
+    };
+    let request = cri::ContainerStatusRequest { container_id: container.id.clone(), verbose: false };
+    Ok(get_client().await.container_status(request).await?.into_inner().status)
+}
+
+/// ID of the pod's ready sandbox.
+pub async fn find_sandbox(namespace: &str, pod_name: &str) -> anyhow::Result<Option<String>> {
+    let filter = cri::PodSandboxFilter {
+        label_selector: HashMap::from([
+            (POD_NAMESPACE_LABEL.to_string(), namespace.to_string()),
+            (POD_NAME_LABEL.to_string(), pod_name.to_string()),
+        ]),
+        state: Some(cri::PodSandboxStateValue { state: cri::PodSandboxState::SandboxReady as i32 }),
+        ..Default::default()
+    };
+    let sandboxes = get_client().await
+        .list_pod_sandbox(cri::ListPodSandboxRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .items;
+    Ok(sandboxes.into_iter().max_by_key(|s| s.created_at).map(|s| s.id))
+}
+
+/// Host network pods report the node IP, all others the sandbox's IP.
+async fn pod_ip(pod: &Pod, pod_sandbox_id: &str) -> Option<String> {
+    if namespaces::host_network(pod) {
+        return node_ip().map(|ip| ip.to_string());
+    }
+    sandbox_ip(pod_sandbox_id).await
+}
+
+async fn sandbox_ip(pod_sandbox_id: &str) -> Option<String> {
+    let request = cri::PodSandboxStatusRequest { pod_sandbox_id: pod_sandbox_id.to_string(), verbose: false };
+    let response = get_client().await
+        .pod_sandbox_status(request)
+        .await
+        .map_err(|e| error!("获取sandbox状态失败: {}", e))
+        .ok()?;
+    let ip = response.get_ref().status.as_ref()?.network.as_ref()?.ip.clone();
+    Some(ip).filter(|ip| !ip.is_empty())
+}
@@ -0,0 +1,42 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, RwLock};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::ResourceExt;
+
+/// Pods this kubelet has admitted, keyed by UID.
+static PODS: RwLock<BTreeMap<String, Pod>> = RwLock::new(BTreeMap::new());
+/// UIDs of admitted pods whose teardown is in progress.
+static TERMINATING: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Admits and registers a pod in one step, so concurrently started pods are
+/// always checked against each other.
+pub fn try_add<E>(pod: &Pod, admit: impl FnOnce(&[Pod]) -> Result<(), E>) -> Result<(), E> {
+    let mut pods = PODS.write().unwrap();
+    let uid = pod.uid().unwrap_or_default();
+    let others: Vec<Pod> = pods.iter().filter(|(k, _)| **k != uid).map(|(_, p)| p.clone()).collect();
+    admit(&others)?;
+    pods.insert(uid, pod.clone());
+    Ok(())
+}
+
+pub fn remove(pod_uid: &str) {
+    PODS.write().unwrap().remove(pod_uid);
+    TERMINATING.lock().unwrap().remove(pod_uid);
+}
+
+/// Claims the teardown of an admitted pod. Returns false for pods this node
+/// never admitted and for pods already being torn down.
+pub fn start_termination(pod_uid: &str) -> bool {
+    PODS.read().unwrap().contains_key(pod_uid) && TERMINATING.lock().unwrap().insert(pod_uid.to_string())
+}
+
+/// Gives up a claimed teardown so a later event can retry it.
+pub fn abort_termination(pod_uid: &str) {
+    TERMINATING.lock().unwrap().remove(pod_uid);
+}
+
+/// Snapshot of the admitted pods.
+pub fn pods() -> Vec<Pod> {
+    PODS.read().unwrap().values().cloned().collect()
+}
@@ -0,0 +1,158 @@
+use std::net::IpAddr;
+
+use k8s_openapi::api::core::v1::{ContainerPort, Pod};
+
+use crate::provider::cri;
+
+pub const CONFLICT_REASON: &str = "NodePorts";
+
+fn container_ports(pod: &Pod) -> Vec<ContainerPort> {
+    pod.spec
+        .iter()
+        .flat_map(|s| s.containers.iter())
+        .flat_map(|c| c.ports.clone().unwrap_or_default())
+        .collect()
+}
+
+fn protocol(port: &ContainerPort) -> cri::Protocol {
+    port.protocol
+        .as_deref()
+        .and_then(cri::Protocol::from_str_name)
+        .unwrap_or(cri::Protocol::Tcp)
+}
+
+/// Port mappings for the sandbox, one per declared container port.
+pub fn port_mappings(pod: &Pod) -> Vec<cri::PortMapping> {
+    container_ports(pod)
+        .iter()
+        .map(|port| cri::PortMapping {
+            protocol: protocol(port) as i32,
+            container_port: port.container_port,
+            host_port: port.host_port.unwrap_or(0),
+            host_ip: port.host_ip.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// A host port in use, with an empty or unspecified IP meaning every address.
+#[derive(Debug, Clone)]
+struct HostPort {
+    ip: Option<IpAddr>,
+    protocol: cri::Protocol,
+    port: i32,
+}
+
+impl HostPort {
+    fn conflicts(&self, other: &HostPort) -> bool {
+        if self.port != other.port || self.protocol != other.protocol {
+            return false;
+        }
+        match (self.ip, other.ip) {
+            (Some(a), Some(b)) if !a.is_unspecified() && !b.is_unspecified() => a == b,
+            _ => true,
+        }
+    }
+}
+
+fn host_ports(pod: &Pod) -> Vec<HostPort> {
+    container_ports(pod)
+        .iter()
+        .filter(|p| p.host_port.unwrap_or(0) > 0)
+        .map(|p| HostPort {
+            ip: p.host_ip.as_deref().and_then(|ip| ip.parse().ok()),
+            protocol: protocol(p),
+            port: p.host_port.unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Fails when one of the pod's host ports is already taken by a running pod.
+pub fn check_host_ports(pod: &Pod, running: &[Pod]) -> Result<(), String> {
+    let wanted = host_ports(pod);
+    if wanted.is_empty() {
+        return Ok(());
+    }
+    for other in running {
+        for used in host_ports(other) {
+            if let Some(port) = wanted.iter().find(|p| p.conflicts(&used)) {
+                return Err(format!(
+                    "host port {}/{} is already used by pod {}",
+                    port.port,
+                    port.protocol.as_str_name(),
+                    other.metadata.name.clone().unwrap_or_default()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::pod_manager;
+    use k8s_openapi::api::core::v1::{Container, PodSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn pod(uid: &str, ports: &[(i32, &str, &str)]) -> Pod {
+        let ports = ports
+            .iter()
+            .map(|(port, protocol, ip)| ContainerPort {
+                container_port: 8080,
+                host_port: Some(*port),
+                protocol: Some(protocol.to_string()),
+                host_ip: Some(ip.to_string()).filter(|ip| !ip.is_empty()),
+                ..Default::default()
+            })
+            .collect();
+        Pod {
+            metadata: ObjectMeta { name: Some(uid.to_string()), uid: Some(uid.to_string()), ..Default::default() },
+            spec: Some(PodSpec {
+                containers: vec![Container { name: "c".to_string(), ports: Some(ports), ..Default::default() }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn conflicts(wanted: &[(i32, &str, &str)], used: &[(i32, &str, &str)]) -> bool {
+        check_host_ports(&pod("new", wanted), &[pod("old", used)]).is_err()
+    }
+
+    #[test]
+    fn conflicts_only_on_the_same_protocol() {
+        assert!(conflicts(&[(80, "TCP", "")], &[(80, "TCP", "")]));
+        assert!(!conflicts(&[(80, "TCP", "")], &[(80, "UDP", "")]));
+        assert!(!conflicts(&[(80, "TCP", "")], &[(81, "TCP", "")]));
+    }
+
+    #[test]
+    fn wildcard_host_ip_overlaps_every_address() {
+        assert!(conflicts(&[(80, "TCP", "")], &[(80, "TCP", "10.0.0.1")]));
+        assert!(conflicts(&[(80, "TCP", "0.0.0.0")], &[(80, "TCP", "10.0.0.1")]));
+        assert!(conflicts(&[(80, "TCP", "10.0.0.1")], &[(80, "TCP", "10.0.0.1")]));
+        assert!(!conflicts(&[(80, "TCP", "10.0.0.1")], &[(80, "TCP", "10.0.0.2")]));
+    }
+
+    #[test]
+    fn container_ports_without_host_port_never_conflict() {
+        assert!(!conflicts(&[(0, "TCP", "")], &[(0, "TCP", "")]));
+        assert!(check_host_ports(&pod("new", &[(80, "TCP", "")]), &[]).is_ok());
+    }
+
+    #[test]
+    fn checks_only_admitted_pods() {
+        let admit = |pod: &Pod| pod_manager::try_add(pod, |running| check_host_ports(pod, running));
+        let first = pod("ports-test-first", &[(30080, "TCP", "")]);
+        let second = pod("ports-test-second", &[(30080, "TCP", "")]);
+        admit(&first).unwrap();
+        // Admitting the same pod again does not conflict with itself.
+        admit(&first).unwrap();
+        assert!(admit(&second).is_err());
+        // A rejected pod does not hold the port.
+        assert!(admit(&pod("ports-test-third", &[(30080, "TCP", "")])).is_err());
+        pod_manager::remove("ports-test-first");
+        admit(&second).unwrap();
+        pod_manager::remove("ports-test-second");
+    }
+}
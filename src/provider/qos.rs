@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{Container, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+use crate::kubelet::config::CgroupDriver;
+use crate::provider::quantity::{parse_quantity, ParsedQuantity};
+
+const GUARANTEED_OOM_SCORE_ADJ: i64 = -997;
+const BESTEFFORT_OOM_SCORE_ADJ: i64 = 1000;
+const KUBEPODS: &str = "kubepods";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QosClass {
+    Guaranteed,
+    Burstable,
+    BestEffort,
+}
+
+impl QosClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QosClass::Guaranteed => "Guaranteed",
+            QosClass::Burstable => "Burstable",
+            QosClass::BestEffort => "BestEffort",
+        }
+    }
+}
+
+fn positive(q: &Quantity) -> Option<ParsedQuantity> {
+    parse_quantity(q).ok().filter(|q| *q > ParsedQuantity::default())
+}
+
+fn all_containers(pod: &Pod) -> Vec<Container> {
+    let spec = pod.spec.clone().unwrap_or_default();
+    let mut containers = spec.containers;
+    containers.extend(spec.init_containers.unwrap_or_default());
+    containers
+}
+
+/// Classifies a pod from the cpu and memory requests and limits of all its containers.
+pub fn pod_qos(pod: &Pod) -> QosClass {
+    let mut requests: BTreeMap<String, ParsedQuantity> = BTreeMap::new();
+    let mut limits: BTreeMap<String, ParsedQuantity> = BTreeMap::new();
+    let mut is_guaranteed = true;
+
+    let supported = |name: &str| name == "cpu" || name == "memory";
+
+    for container in all_containers(pod) {
+        let resources = container.resources.unwrap_or_default();
+        for (name, q) in resources.requests.unwrap_or_default() {
+            if let Some(q) = positive(&q).filter(|_| supported(&name)) {
+                *requests.entry(name).or_default() += q;
+            }
+        }
+        let mut found = 0;
+        for (name, q) in resources.limits.unwrap_or_default() {
+            if let Some(q) = positive(&q).filter(|_| supported(&name)) {
+                found += 1;
+                *limits.entry(name).or_default() += q;
+            }
+        }
+        if found < 2 {
+            is_guaranteed = false;
+        }
+    }
+
+    if requests.is_empty() && limits.is_empty() {
+        return QosClass::BestEffort;
+    }
+    if is_guaranteed
+        && requests.len() == limits.len()
+        && requests.iter().all(|(name, q)| limits.get(name) == Some(q))
+    {
+        return QosClass::Guaranteed;
+    }
+    QosClass::Burstable
+}
+
+/// OOM score adjustment for a container, mirroring the kubelet's policy.
+pub fn container_oom_score_adjust(
+    pod: &Pod,
+    container: &Container,
+    memory_capacity: i64,
+) -> i64 {
+    let spec = pod.spec.as_ref();
+    if spec.and_then(|s| s.priority_class_name.as_deref()) == Some("system-node-critical") {
+        return GUARANTEED_OOM_SCORE_ADJ;
+    }
+    match pod_qos(pod) {
+        QosClass::Guaranteed => GUARANTEED_OOM_SCORE_ADJ,
+        QosClass::BestEffort => BESTEFFORT_OOM_SCORE_ADJ,
+        QosClass::Burstable => {
+            let memory_request = container
+                .resources
+                .as_ref()
+                .and_then(|r| r.requests.as_ref())
+                .and_then(|r| r.get("memory"))
+                .and_then(|q| parse_quantity(q).ok())
+                .map(|q| q.value())
+                .unwrap_or(0);
+            if memory_capacity <= 0 {
+                return BESTEFFORT_OOM_SCORE_ADJ - 1;
+            }
+            let adj = 1000 - (1000 * memory_request) / memory_capacity;
+            // Burstable containers must stay strictly between guaranteed and best-effort.
+            if adj < 1000 + GUARANTEED_OOM_SCORE_ADJ {
+                return 1000 + GUARANTEED_OOM_SCORE_ADJ;
+            }
+            if adj == BESTEFFORT_OOM_SCORE_ADJ {
+                return adj - 1;
+            }
+            adj
+        }
+    }
+}
+
+/// Cgroup parent for the pod sandbox. Guaranteed pods live directly under
+/// `kubepods`, the other classes under `kubepods/<qos>`.
+pub fn pod_cgroup_parent(qos: QosClass, pod_uid: &str, driver: CgroupDriver) -> String {
+    let mut components = vec![KUBEPODS.to_string()];
+    match qos {
+        QosClass::Guaranteed => {}
+        QosClass::Burstable => components.push("burstable".to_string()),
+        QosClass::BestEffort => components.push("besteffort".to_string()),
+    }
+    components.push(format!("pod{}", pod_uid));
+    match driver {
+        CgroupDriver::Cgroupfs => format!("/{}", components.join("/")),
+        CgroupDriver::Systemd => to_systemd(&components),
+    }
+}
+
+/// Converts `[kubepods, burstable, pod<uid>]` into the nested slice path
+/// `/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod<uid>.slice`.
+fn to_systemd(components: &[String]) -> String {
+    let mut path = String::new();
+    let mut prefix = String::new();
+    for component in components {
+        let escaped = component.replace('-', "_");
+        prefix = if prefix.is_empty() { escaped } else { format!("{}-{}", prefix, escaped) };
+        path.push_str(&format!("/{}.slice", prefix));
+    }
+    path
+}
+
+/// Total machine memory from `/proc/meminfo`, in bytes.
+pub fn machine_memory_capacity() -> i64 {
+    std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                let kb = line.strip_prefix("MemTotal:")?.trim().strip_suffix("kB")?;
+                kb.trim().parse::<i64>().ok()
+            })
+        })
+        .map(|kb| kb * 1024)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodSpec, ResourceRequirements};
+
+    fn resources(pairs: &[(&str, &str)]) -> Option<BTreeMap<String, Quantity>> {
+        Some(pairs.iter().map(|(name, q)| (name.to_string(), Quantity(q.to_string()))).collect())
+    }
+
+    fn container(requests: &[(&str, &str)], limits: &[(&str, &str)]) -> Container {
+        Container {
+            name: "c".to_string(),
+            resources: Some(ResourceRequirements {
+                requests: resources(requests),
+                limits: resources(limits),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn pod(containers: Vec<Container>, init_containers: Vec<Container>) -> Pod {
+        Pod {
+            spec: Some(PodSpec { containers, init_containers: Some(init_containers), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    const FULL: &[(&str, &str)] = &[("cpu", "500m"), ("memory", "128Mi")];
+
+    #[test]
+    fn classifies_pods() {
+        let cases = [
+            ("no resources", pod(vec![container(&[], &[])], vec![]), QosClass::BestEffort),
+            (
+                "zero requests",
+                pod(vec![container(&[("cpu", "0"), ("memory", "0")], &[])], vec![]),
+                QosClass::BestEffort,
+            ),
+            (
+                "only unsupported resources",
+                pod(vec![container(&[("ephemeral-storage", "1Gi")], &[("ephemeral-storage", "1Gi")])], vec![]),
+                QosClass::BestEffort,
+            ),
+            ("equal requests and limits", pod(vec![container(FULL, FULL)], vec![]), QosClass::Guaranteed),
+            (
+                "equal in different units",
+                pod(vec![container(&[("cpu", "0.5"), ("memory", "128Mi")], FULL)], vec![]),
+                QosClass::Guaranteed,
+            ),
+            (
+                "requests below limits",
+                pod(vec![container(&[("cpu", "100m"), ("memory", "128Mi")], FULL)], vec![]),
+                QosClass::Burstable,
+            ),
+            ("only requests", pod(vec![container(FULL, &[])], vec![]), QosClass::Burstable),
+            (
+                "missing memory limit",
+                pod(vec![container(&[("cpu", "500m")], &[("cpu", "500m")])], vec![]),
+                QosClass::Burstable,
+            ),
+            (
+                "one container without limits",
+                pod(vec![container(FULL, FULL), container(&[], &[])], vec![]),
+                QosClass::Burstable,
+            ),
+            (
+                "init container without limits",
+                pod(vec![container(FULL, FULL)], vec![container(&[], &[])]),
+                QosClass::Burstable,
+            ),
+        ];
+        for (name, pod, expected) in cases {
+            assert_eq!(pod_qos(&pod), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn clamps_oom_score_adjust() {
+        const GI: i64 = 1 << 30;
+        let score = |requests: &[(&str, &str)], capacity: i64| {
+            let container = container(requests, &[]);
+            container_oom_score_adjust(&pod(vec![container.clone()], vec![]), &container, capacity)
+        };
+        let guaranteed = container(FULL, FULL);
+        assert_eq!(container_oom_score_adjust(&pod(vec![guaranteed.clone()], vec![]), &guaranteed, GI), -997);
+        let best_effort = container(&[], &[]);
+        assert_eq!(container_oom_score_adjust(&pod(vec![best_effort.clone()], vec![]), &best_effort, GI), 1000);
+
+        assert_eq!(score(&[("memory", "512Mi")], 2 * GI), 750);
+        // A tiny request would score like best-effort and a huge one like guaranteed.
+        assert_eq!(score(&[("cpu", "100m")], GI), 999);
+        assert_eq!(score(&[("memory", "1")], GI), 999);
+        assert_eq!(score(&[("memory", "1Gi")], GI), 3);
+        assert_eq!(score(&[("memory", "4Gi")], GI), 3);
+        assert_eq!(score(&[("memory", "1Gi")], 0), 999);
+
+        let mut critical = pod(vec![best_effort.clone()], vec![]);
+        critical.spec.as_mut().unwrap().priority_class_name = Some("system-node-critical".to_string());
+        assert_eq!(container_oom_score_adjust(&critical, &best_effort, GI), -997);
+    }
+
+    #[test]
+    fn builds_cgroup_parents() {
+        let uid = "1b2c-3d";
+        assert_eq!(pod_cgroup_parent(QosClass::Guaranteed, uid, CgroupDriver::Cgroupfs), "/kubepods/pod1b2c-3d");
+        assert_eq!(
+            pod_cgroup_parent(QosClass::Burstable, uid, CgroupDriver::Cgroupfs),
+            "/kubepods/burstable/pod1b2c-3d"
+        );
+        assert_eq!(
+            pod_cgroup_parent(QosClass::Guaranteed, uid, CgroupDriver::Systemd),
+            "/kubepods.slice/kubepods-pod1b2c_3d.slice"
+        );
+        assert_eq!(
+            pod_cgroup_parent(QosClass::BestEffort, uid, CgroupDriver::Systemd),
+            "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod1b2c_3d.slice"
+        );
+    }
+}
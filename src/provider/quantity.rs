@@ -0,0 +1,159 @@
+use std::fmt;
+
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+const NANO: i128 = 1_000_000_000;
+
+/// A parsed resource quantity, stored with nano precision.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ParsedQuantity {
+    nanos: i128,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct QuantityError(String);
+
+impl fmt::Display for QuantityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid quantity {:?}", self.0)
+    }
+}
+
+impl std::error::Error for QuantityError {}
+
+impl ParsedQuantity {
+    /// The value rounded up to the nearest integer, e.g. bytes or whole cores.
+    pub fn value(&self) -> i64 {
+        div_ceil(self.nanos, NANO) as i64
+    }
+
+    /// The value in thousandths rounded up, e.g. millicores.
+    pub fn milli_value(&self) -> i64 {
+        div_ceil(self.nanos, NANO / 1000) as i64
+    }
+}
+
+impl std::ops::AddAssign for ParsedQuantity {
+    fn add_assign(&mut self, other: Self) {
+        self.nanos += other.nanos;
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b > 0 { q + 1 } else { q }
+}
+
+/// Parses the Kubernetes quantity serialization format, e.g. `100m`, `1.5Gi`,
+/// `2G`, `1e3` or `12E-1`.
+pub fn parse(input: &str) -> Result<ParsedQuantity, QuantityError> {
+    let err = || QuantityError(input.to_string());
+    let s = input.trim();
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    let number_end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(number_end);
+    if number.is_empty() || number == "." || number.matches('.').count() > 1 {
+        return Err(err());
+    }
+    let (int_part, frac_part) = number.split_once('.').unwrap_or((number, ""));
+    let digits = format!("{}{}", int_part, frac_part);
+    let mantissa: i128 = digits.parse().map_err(|_| err())?;
+    let mut exponent = -(frac_part.len() as i32);
+
+    let mut multiplier: i128 = 1;
+    match suffix {
+        "" => {}
+        "n" => exponent -= 9,
+        "u" => exponent -= 6,
+        "m" => exponent -= 3,
+        "k" => exponent += 3,
+        "M" => exponent += 6,
+        "G" => exponent += 9,
+        "T" => exponent += 12,
+        "P" => exponent += 15,
+        "E" => exponent += 18,
+        "Ki" => multiplier = 1 << 10,
+        "Mi" => multiplier = 1 << 20,
+        "Gi" => multiplier = 1 << 30,
+        "Ti" => multiplier = 1 << 40,
+        "Pi" => multiplier = 1 << 50,
+        "Ei" => multiplier = 1 << 60,
+        _ => {
+            let exp = suffix
+                .strip_prefix('e')
+                .or_else(|| suffix.strip_prefix('E'))
+                .ok_or_else(err)?;
+            let exp: i32 = exp.parse().map_err(|_| err())?;
+            exponent += exp;
+        }
+    }
+
+    let scaled = mantissa.checked_mul(multiplier).ok_or_else(err)?;
+    let shift = exponent + 9;
+    let nanos = if shift >= 0 {
+        10i128
+            .checked_pow(shift as u32)
+            .and_then(|p| scaled.checked_mul(p))
+            .ok_or_else(err)?
+    } else {
+        // Anything finer than a nano unit is rounded up, as the API server does.
+        let divisor = 10i128.checked_pow((-shift) as u32).unwrap_or(i128::MAX);
+        div_ceil(scaled, divisor)
+    };
+    Ok(ParsedQuantity { nanos: if negative { -nanos } else { nanos } })
+}
+
+pub fn parse_quantity(q: &Quantity) -> Result<ParsedQuantity, QuantityError> {
+    parse(&q.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimal_si() {
+        assert_eq!(parse("100m").unwrap().milli_value(), 100);
+        assert_eq!(parse("2").unwrap().milli_value(), 2000);
+        assert_eq!(parse("1.5").unwrap().milli_value(), 1500);
+        assert_eq!(parse("2G").unwrap().value(), 2_000_000_000);
+        assert_eq!(parse("1k").unwrap().value(), 1000);
+        assert_eq!(parse("500n").unwrap().milli_value(), 1);
+    }
+
+    #[test]
+    fn parses_binary_si() {
+        assert_eq!(parse("1Ki").unwrap().value(), 1024);
+        assert_eq!(parse("128Mi").unwrap().value(), 128 * 1024 * 1024);
+        assert_eq!(parse("1.5Gi").unwrap().value(), 1536 * 1024 * 1024);
+    }
+
+    #[test]
+    fn parses_exponents() {
+        assert_eq!(parse("1e3").unwrap().value(), 1000);
+        assert_eq!(parse("12E-1").unwrap().milli_value(), 1200);
+        assert_eq!(parse("1E").unwrap().value(), 1_000_000_000_000_000_000);
+    }
+
+    #[test]
+    fn rounds_up() {
+        assert_eq!(parse("0.1").unwrap().value(), 1);
+        assert_eq!(parse("0.0001").unwrap().milli_value(), 1);
+        assert_eq!(parse("1.5m").unwrap().milli_value(), 2);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse("").is_err());
+        assert!(parse("Mi").is_err());
+        assert!(parse("1.2.3").is_err());
+        assert!(parse("10Xi").is_err());
+    }
+}
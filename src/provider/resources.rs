@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Container;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+use crate::provider::cri;
+use crate::provider::quantity::{self, parse_quantity, ParsedQuantity};
+
+pub const MIN_SHARES: i64 = 2;
+pub const MAX_SHARES: i64 = 262144;
+pub const SHARES_PER_CPU: i64 = 1024;
+pub const MILLI_CPU_TO_CPU: i64 = 1000;
+pub const QUOTA_PERIOD: i64 = 100000;
+pub const MIN_QUOTA_PERIOD: i64 = 1000;
+
+const HUGEPAGES_PREFIX: &str = "hugepages-";
+const HUGEPAGES_SYSFS: &str = "/sys/kernel/mm/hugepages";
+
+/// Converts a CPU request in millicores to cgroup cpu shares.
+pub fn milli_cpu_to_shares(milli_cpu: i64) -> i64 {
+    if milli_cpu == 0 {
+        return MIN_SHARES;
+    }
+    let shares = milli_cpu * SHARES_PER_CPU / MILLI_CPU_TO_CPU;
+    shares.clamp(MIN_SHARES, MAX_SHARES)
+}
+
+/// Converts a CPU limit in millicores to a CFS quota for the given period.
+pub fn milli_cpu_to_quota(milli_cpu: i64, period: i64) -> i64 {
+    if milli_cpu == 0 {
+        return 0;
+    }
+    let quota = milli_cpu * period / MILLI_CPU_TO_CPU;
+    quota.max(MIN_QUOTA_PERIOD)
+}
+
+fn lookup(resources: Option<&BTreeMap<String, Quantity>>, name: &str) -> Option<ParsedQuantity> {
+    resources
+        .and_then(|r| r.get(name))
+        .and_then(|q| parse_quantity(q).ok())
+}
+
+/// Builds the cgroup settings for a container from its requests and limits.
+pub fn linux_container_resources(container: &Container) -> cri::LinuxContainerResources {
+    let requirements = container.resources.clone().unwrap_or_default();
+    let requests = requirements.requests.as_ref();
+    let limits = requirements.limits.as_ref();
+
+    let cpu_limit = lookup(limits, "cpu");
+    // The API server defaults a missing request to the limit.
+    let cpu_request = lookup(requests, "cpu").or(cpu_limit);
+    let memory_limit = lookup(limits, "memory");
+
+    let mut resources = cri::LinuxContainerResources {
+        cpu_shares: milli_cpu_to_shares(cpu_request.map(|q| q.milli_value()).unwrap_or(0)),
+        ..Default::default()
+    };
+    if let Some(limit) = cpu_limit {
+        resources.cpu_period = QUOTA_PERIOD;
+        resources.cpu_quota = milli_cpu_to_quota(limit.milli_value(), QUOTA_PERIOD);
+    }
+    if let Some(limit) = memory_limit {
+        resources.memory_limit_in_bytes = limit.value();
+    }
+    resources.hugepage_limits = hugepage_limits(limits, &supported_hugepage_sizes());
+    resources
+}
+
+/// Every page size supported by the host gets an entry so that containers
+/// without a hugepage limit cannot allocate any.
+fn hugepage_limits(
+    limits: Option<&BTreeMap<String, Quantity>>,
+    supported: &[u64],
+) -> Vec<cri::HugepageLimit> {
+    let mut by_size: BTreeMap<u64, u64> = supported.iter().map(|size| (*size, 0)).collect();
+    for (name, quantity) in limits.into_iter().flatten() {
+        let Some(size) = name.strip_prefix(HUGEPAGES_PREFIX) else {
+            continue;
+        };
+        if let (Ok(size), Ok(limit)) = (quantity::parse(size), parse_quantity(quantity)) {
+            by_size.insert(size.value() as u64, limit.value().max(0) as u64);
+        }
+    }
+    by_size
+        .into_iter()
+        .map(|(size, limit)| cri::HugepageLimit { page_size: hugepage_unit_size(size), limit })
+        .collect()
+}
+
+fn supported_hugepage_sizes() -> Vec<u64> {
+    let Ok(entries) = std::fs::read_dir(HUGEPAGES_SYSFS) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let kb: u64 = name.strip_prefix("hugepages-")?.strip_suffix("kB")?.parse().ok()?;
+            Some(kb * 1024)
+        })
+        .collect()
+}
+
+/// Formats a page size the way the hugetlb cgroup names its files, e.g. `2MB`.
+pub fn hugepage_unit_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = size;
+    let mut unit = 0;
+    while value >= 1024 && value.is_multiple_of(1024) && unit < UNITS.len() - 1 {
+        value /= 1024;
+        unit += 1;
+    }
+    format!("{}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::ResourceRequirements;
+
+    use super::*;
+
+    fn container(requests: &[(&str, &str)], limits: &[(&str, &str)]) -> Container {
+        let to_map = |items: &[(&str, &str)]| {
+            items
+                .iter()
+                .map(|(k, v)| (k.to_string(), Quantity(v.to_string())))
+                .collect::<BTreeMap<_, _>>()
+        };
+        Container {
+            name: "test".to_string(),
+            resources: Some(ResourceRequirements {
+                requests: Some(to_map(requests)),
+                limits: Some(to_map(limits)),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn converts_cpu_shares() {
+        assert_eq!(milli_cpu_to_shares(0), MIN_SHARES);
+        assert_eq!(milli_cpu_to_shares(1), MIN_SHARES);
+        assert_eq!(milli_cpu_to_shares(100), 102);
+        assert_eq!(milli_cpu_to_shares(1000), 1024);
+        assert_eq!(milli_cpu_to_shares(1_000_000), MAX_SHARES);
+    }
+
+    #[test]
+    fn converts_cpu_quota() {
+        assert_eq!(milli_cpu_to_quota(0, QUOTA_PERIOD), 0);
+        assert_eq!(milli_cpu_to_quota(5, QUOTA_PERIOD), MIN_QUOTA_PERIOD);
+        assert_eq!(milli_cpu_to_quota(250, QUOTA_PERIOD), 25000);
+        assert_eq!(milli_cpu_to_quota(2000, QUOTA_PERIOD), 200000);
+    }
+
+    #[test]
+    fn maps_requests_and_limits() {
+        let c = container(&[("cpu", "250m")], &[("cpu", "500m"), ("memory", "128Mi")]);
+        let r = linux_container_resources(&c);
+        assert_eq!(r.cpu_shares, 256);
+        assert_eq!(r.cpu_period, QUOTA_PERIOD);
+        assert_eq!(r.cpu_quota, 50000);
+        assert_eq!(r.memory_limit_in_bytes, 128 * 1024 * 1024);
+    }
+
+    #[test]
+    fn request_defaults_to_limit() {
+        let r = linux_container_resources(&container(&[], &[("cpu", "1")]));
+        assert_eq!(r.cpu_shares, 1024);
+        let r = linux_container_resources(&container(&[], &[]));
+        assert_eq!(r.cpu_shares, MIN_SHARES);
+        assert_eq!(r.cpu_quota, 0);
+        assert_eq!(r.memory_limit_in_bytes, 0);
+    }
+
+    #[test]
+    fn maps_hugepages() {
+        let limits: BTreeMap<String, Quantity> =
+            [("hugepages-2Mi".to_string(), Quantity("100Mi".to_string()))].into();
+        let got = hugepage_limits(Some(&limits), &[2 << 20, 1 << 30]);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].page_size, "2MB");
+        assert_eq!(got[0].limit, 100 << 20);
+        assert_eq!(got[1].page_size, "1GB");
+        assert_eq!(got[1].limit, 0);
+    }
+}
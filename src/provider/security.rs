@@ -0,0 +1,243 @@
+use std::path::Path;
+
+use k8s_openapi::api::core::v1::{Container, Pod, SeccompProfile, SecurityContext, SELinuxOptions};
+
+use crate::kubelet::config::config;
+use crate::provider::{cri, namespaces};
+use crate::provider::cri::security_profile::ProfileType;
+
+/// AppArmor profiles are read from this per-container annotation only: the
+/// v1_25 API has no `appArmorProfile` security context field, so there is
+/// nothing else to honour.
+const APPARMOR_ANNOTATION_PREFIX: &str = "container.apparmor.security.beta.kubernetes.io/";
+
+const DEFAULT_MASKED_PATHS: &[&str] = &[
+    "/proc/acpi",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/proc/sched_debug",
+    "/proc/scsi",
+    "/sys/firmware",
+];
+
+const DEFAULT_READONLY_PATHS: &[&str] = &[
+    "/proc/asound",
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+];
+
+/// The user an image runs as when the container does not override it.
+#[derive(Clone, Debug, Default)]
+pub struct ImageUser {
+    pub uid: Option<i64>,
+    pub username: String,
+}
+
+impl ImageUser {
+    /// Fails without an image status, which must not be mistaken for an
+    /// image that runs as root; the caller can retry once the image is known.
+    pub fn from_image(image: Option<&cri::Image>) -> anyhow::Result<Self> {
+        let Some(image) = image else {
+            anyhow::bail!("image status unavailable");
+        };
+        let user = if let Some(uid) = &image.uid {
+            ImageUser { uid: Some(uid.value), username: String::new() }
+        } else if !image.username.is_empty() {
+            ImageUser { uid: None, username: image.username.clone() }
+        } else {
+            // An image without a configured user runs as root.
+            ImageUser { uid: Some(0), username: String::new() }
+        };
+        Ok(user)
+    }
+}
+
+/// Merges the pod-level security context into the container's: fields the
+/// container sets win, the rest are inherited from the pod.
+pub fn effective_security_context(pod: &Pod, container: &Container) -> SecurityContext {
+    let mut effective = container.security_context.clone().unwrap_or_default();
+    let Some(pod_sc) = pod.spec.as_ref().and_then(|s| s.security_context.as_ref()) else {
+        return effective;
+    };
+    if effective.run_as_user.is_none() {
+        effective.run_as_user = pod_sc.run_as_user;
+    }
+    if effective.run_as_group.is_none() {
+        effective.run_as_group = pod_sc.run_as_group;
+    }
+    if effective.run_as_non_root.is_none() {
+        effective.run_as_non_root = pod_sc.run_as_non_root;
+    }
+    if effective.se_linux_options.is_none() {
+        effective.se_linux_options = pod_sc.se_linux_options.clone();
+    }
+    if effective.seccomp_profile.is_none() {
+        effective.seccomp_profile = pod_sc.seccomp_profile.clone();
+    }
+    effective
+}
+
+/// The fsGroup followed by the pod's supplemental groups.
+pub fn supplemental_groups(pod: &Pod) -> Vec<i64> {
+    let Some(pod_sc) = pod.spec.as_ref().and_then(|s| s.security_context.as_ref()) else {
+        return vec![];
+    };
+    let mut groups: Vec<i64> = pod_sc.fs_group.into_iter().collect();
+    groups.extend(pod_sc.supplemental_groups.clone().unwrap_or_default());
+    groups
+}
+
+/// Security settings for the sandbox itself, derived from the pod securityContext.
+pub fn linux_sandbox_security_context(pod: &Pod) -> cri::LinuxSandboxSecurityContext {
+    let spec = pod.spec.clone().unwrap_or_default();
+    let pod_sc = spec.security_context.clone().unwrap_or_default();
+    let privileged = spec
+        .containers
+        .iter()
+        .chain(spec.init_containers.iter().flatten())
+        .any(|c| c.security_context.as_ref().and_then(|sc| sc.privileged) == Some(true));
+    cri::LinuxSandboxSecurityContext {
+        namespace_options: Some(namespaces::namespaces_for_pod(pod)),
+        selinux_options: pod_sc.se_linux_options.as_ref().map(selinux_option),
+        run_as_user: pod_sc.run_as_user.map(|value| cri::Int64Value { value }),
+        run_as_group: pod_sc.run_as_group.map(|value| cri::Int64Value { value }),
+        supplemental_groups: supplemental_groups(pod),
+        privileged,
+        seccomp: Some(seccomp_profile(pod_sc.seccomp_profile.as_ref())),
+        ..Default::default()
+    }
+}
+
+/// Rejects containers that ask for `runAsNonRoot` but would end up running as root.
+pub fn verify_run_as_non_root(
+    pod: &Pod,
+    container: &Container,
+    sc: &SecurityContext,
+    image_user: &ImageUser,
+) -> anyhow::Result<()> {
+    if sc.run_as_non_root != Some(true) {
+        return Ok(());
+    }
+    let pod_name = format!(
+        "{}/{}",
+        pod.metadata.namespace.clone().unwrap_or_default(),
+        pod.metadata.name.clone().unwrap_or_default()
+    );
+    if let Some(uid) = sc.run_as_user {
+        if uid == 0 {
+            anyhow::bail!(
+                "container's runAsUser breaks non-root policy (pod: {:?}, container: {})",
+                pod_name,
+                container.name
+            );
+        }
+        return Ok(());
+    }
+    match (image_user.uid, image_user.username.as_str()) {
+        (Some(0), _) => anyhow::bail!(
+            "container has runAsNonRoot and image will run as root (pod: {:?}, container: {})",
+            pod_name,
+            container.name
+        ),
+        (None, username) if !username.is_empty() => anyhow::bail!(
+            "container has runAsNonRoot and image has non-numeric user ({}), cannot verify user is non-root (pod: {:?}, container: {})",
+            username,
+            pod_name,
+            container.name
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Translates the effective security context into its CRI representation.
+#[allow(deprecated)]
+pub fn linux_container_security_context(
+    pod: &Pod,
+    container: &Container,
+    sc: &SecurityContext,
+) -> cri::LinuxContainerSecurityContext {
+    let privileged = sc.privileged.unwrap_or(false);
+    let capabilities = sc.capabilities.as_ref().map(|caps| cri::Capability {
+        add_capabilities: caps.add.clone().unwrap_or_default(),
+        drop_capabilities: caps.drop.clone().unwrap_or_default(),
+        add_ambient_capabilities: vec![],
+    });
+    let (masked_paths, readonly_paths) = match sc.proc_mount.as_deref() {
+        Some("Unmasked") => (vec![], vec![]),
+        _ => (
+            DEFAULT_MASKED_PATHS.iter().map(|p| p.to_string()).collect(),
+            DEFAULT_READONLY_PATHS.iter().map(|p| p.to_string()).collect(),
+        ),
+    };
+
+    cri::LinuxContainerSecurityContext {
+        capabilities,
+        privileged,
+        namespace_options: Some(namespaces::namespaces_for_pod(pod)),
+        selinux_options: sc.se_linux_options.as_ref().map(selinux_option),
+        run_as_user: sc.run_as_user.map(|value| cri::Int64Value { value }),
+        run_as_group: sc.run_as_group.map(|value| cri::Int64Value { value }),
+        supplemental_groups: supplemental_groups(pod),
+        readonly_rootfs: sc.read_only_root_filesystem.unwrap_or(false),
+        no_new_privs: !privileged && sc.allow_privilege_escalation == Some(false),
+        masked_paths,
+        readonly_paths,
+        seccomp: Some(seccomp_profile(sc.seccomp_profile.as_ref())),
+        apparmor: apparmor_profile(pod, &container.name),
+        ..Default::default()
+    }
+}
+
+pub fn selinux_option(options: &SELinuxOptions) -> cri::SeLinuxOption {
+    cri::SeLinuxOption {
+        user: options.user.clone().unwrap_or_default(),
+        role: options.role.clone().unwrap_or_default(),
+        r#type: options.type_.clone().unwrap_or_default(),
+        level: options.level.clone().unwrap_or_default(),
+    }
+}
+
+/// Maps a seccomp profile. An unset profile is unconfined unless
+/// `seccompDefault` is enabled, in which case the runtime default applies.
+pub fn seccomp_profile(profile: Option<&SeccompProfile>) -> cri::SecurityProfile {
+    let Some(profile) = profile else {
+        if config().seccomp_default {
+            return security_profile(ProfileType::RuntimeDefault, String::new());
+        }
+        return security_profile(ProfileType::Unconfined, String::new());
+    };
+    match profile.type_.as_str() {
+        "RuntimeDefault" => security_profile(ProfileType::RuntimeDefault, String::new()),
+        "Localhost" => {
+            let local = profile.localhost_profile.clone().unwrap_or_default();
+            let path = Path::new(&config().root_dir).join("seccomp").join(local);
+            security_profile(ProfileType::Localhost, path.to_string_lossy().into_owned())
+        }
+        _ => security_profile(ProfileType::Unconfined, String::new()),
+    }
+}
+
+/// AppArmor is configured per container through the
+/// `container.apparmor.security.beta.kubernetes.io/<container>` annotation,
+/// with values `runtime/default`, `localhost/<profile>` or `unconfined`.
+fn apparmor_profile(pod: &Pod, container_name: &str) -> Option<cri::SecurityProfile> {
+    let key = format!("{}{}", APPARMOR_ANNOTATION_PREFIX, container_name);
+    let value = pod.metadata.annotations.as_ref()?.get(&key)?;
+    match value.as_str() {
+        "runtime/default" => Some(security_profile(ProfileType::RuntimeDefault, String::new())),
+        "unconfined" => Some(security_profile(ProfileType::Unconfined, String::new())),
+        other => other
+            .strip_prefix("localhost/")
+            .map(|name| security_profile(ProfileType::Localhost, name.to_string())),
+    }
+}
+
+fn security_profile(profile_type: ProfileType, localhost_ref: String) -> cri::SecurityProfile {
+    cri::SecurityProfile { profile_type: profile_type as i32, localhost_ref }
+}
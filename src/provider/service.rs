@@ -0,0 +1,70 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Service;
+use kube::Api;
+use kube::api::ListParams;
+use kube::runtime::{reflector, watcher};
+use kube::runtime::reflector::Store;
+use tokio::time;
+use tracing::*;
+
+use crate::kubelet::client;
+
+static SERVICES: RwLock<Option<Store<Service>>> = RwLock::new(None);
+/// How long a container waits for the initial service list.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keeps a cluster-wide cache of Services used to build service environment variables.
+pub async fn watch_services() -> anyhow::Result<()> {
+    let mut rotated = client::rotated();
+    loop {
+        // A new client per watch, so a rotated certificate is used once the old watch is dropped.
+        let services: Api<Service> = Api::all(client::client().await?);
+        let (reader, writer) = reflector::store();
+        let mut stream = Box::pin(reflector(writer, watcher(services, ListParams::default())));
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => match event {
+                    Some(event) => event,
+                    None => return Ok(()),
+                },
+                _ = rotated.changed() => {
+                    info!("客户端证书已轮换, 重新建立service watch");
+                    break;
+                }
+            };
+            match event {
+                Ok(watcher::Event::Restarted(list)) => {
+                    info!("service cache synced, {} services", list.len());
+                    *SERVICES.write().unwrap() = Some(reader.clone());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("service watch failed: {}", e);
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the cached services once the initial list has been received.
+/// Fails after [`SYNC_TIMEOUT`] rather than start a container without its
+/// service variables.
+pub async fn services() -> anyhow::Result<Vec<Service>> {
+    let synced = async {
+        loop {
+            let store = SERVICES.read().unwrap().clone();
+            if let Some(store) = store {
+                return store.state().iter().map(|s| s.as_ref().clone()).collect();
+            }
+            debug!("waiting for service cache to sync");
+            time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    time::timeout(SYNC_TIMEOUT, synced)
+        .await
+        .map_err(|_| anyhow::anyhow!("services have not yet been read at least once, cannot construct envvars"))
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use k8s_openapi::api::core::v1::Pod;
+
+pub const FORBIDDEN_REASON: &str = "SysctlForbidden";
+
+/// Sysctls that are namespaced and cannot affect other pods or the node.
+const SAFE_SYSCTLS: &[&str] = &[
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.ip_unprivileged_port_start",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Namespace {
+    Ipc,
+    Net,
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Namespace::Ipc => write!(f, "IPC"),
+            Namespace::Net => write!(f, "Network"),
+        }
+    }
+}
+
+fn namespaced_by(sysctl: &str) -> Option<Namespace> {
+    if sysctl == "kernel.sem" {
+        return Some(Namespace::Ipc);
+    }
+    const PREFIXES: &[(&str, Namespace)] = &[
+        ("kernel.shm", Namespace::Ipc),
+        ("kernel.msg", Namespace::Ipc),
+        ("fs.mqueue.", Namespace::Ipc),
+        ("net.", Namespace::Net),
+    ];
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| sysctl.starts_with(prefix))
+        .map(|(_, ns)| *ns)
+}
+
+/// Sysctl names and `prefix*` patterns a pod is allowed to set.
+pub struct PatternAllowlist {
+    sysctls: BTreeMap<String, Namespace>,
+    prefixes: BTreeMap<String, Namespace>,
+}
+
+impl PatternAllowlist {
+    /// Builds the allowlist from the safe set plus `allowedUnsafeSysctls`.
+    /// Patterns that are not namespaced can never be allowed.
+    pub fn new(allowed_unsafe: &[String]) -> anyhow::Result<Self> {
+        let mut allowlist = PatternAllowlist {
+            sysctls: BTreeMap::new(),
+            prefixes: BTreeMap::new(),
+        };
+        let patterns = SAFE_SYSCTLS.iter().map(|s| s.to_string()).chain(allowed_unsafe.iter().cloned());
+        for pattern in patterns {
+            let ns = namespaced_by(&pattern)
+                .ok_or_else(|| anyhow::anyhow!("the sysctl {:?} are not known to be namespaced", pattern))?;
+            match pattern.strip_suffix('*') {
+                Some(prefix) => allowlist.prefixes.insert(prefix.to_string(), ns),
+                None => allowlist.sysctls.insert(pattern, ns),
+            };
+        }
+        Ok(allowlist)
+    }
+
+    fn validate(&self, sysctl: &str, host_net: bool, host_ipc: bool) -> Result<(), String> {
+        let ns = self.sysctls.get(sysctl).copied().or_else(|| {
+            self.prefixes
+                .iter()
+                .find(|(prefix, _)| sysctl.starts_with(prefix.as_str()))
+                .map(|(_, ns)| *ns)
+        });
+        match ns {
+            Some(Namespace::Ipc) if host_ipc => {
+                Err(format!("{:?} not allowed with host {} enabled", sysctl, Namespace::Ipc))
+            }
+            Some(Namespace::Net) if host_net => {
+                Err(format!("{:?} not allowed with host {} enabled", sysctl, Namespace::Net))
+            }
+            Some(_) => Ok(()),
+            None => Err(format!("{:?} not allowlisted", sysctl)),
+        }
+    }
+
+    /// Checks every sysctl requested by the pod, returning the rejection message.
+    pub fn admit(&self, pod: &Pod) -> Result<(), String> {
+        let Some(spec) = pod.spec.as_ref() else {
+            return Ok(());
+        };
+        let sysctls = spec
+            .security_context
+            .as_ref()
+            .and_then(|sc| sc.sysctls.clone())
+            .unwrap_or_default();
+        let host_net = spec.host_network.unwrap_or(false);
+        let host_ipc = spec.host_ipc.unwrap_or(false);
+        for sysctl in sysctls {
+            self.validate(&sysctl.name, host_net, host_ipc)
+                .map_err(|e| format!("forbidden sysctl: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{PodSecurityContext, PodSpec, Sysctl};
+
+    use super::*;
+
+    fn allowlist(allowed_unsafe: &[&str]) -> PatternAllowlist {
+        PatternAllowlist::new(&allowed_unsafe.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn pod(sysctls: &[&str], host_network: bool) -> Pod {
+        Pod {
+            spec: Some(PodSpec {
+                host_network: Some(host_network),
+                security_context: Some(PodSecurityContext {
+                    sysctls: Some(sysctls.iter().map(|s| Sysctl { name: s.to_string(), value: "1".to_string() }).collect()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_exact_names() {
+        let allowlist = allowlist(&["net.core.somaxconn"]);
+        assert!(allowlist.validate("kernel.shm_rmid_forced", false, false).is_ok());
+        assert!(allowlist.validate("net.core.somaxconn", false, false).is_ok());
+        assert!(allowlist.validate("net.core.somaxconn_extra", false, false).is_err());
+        assert!(allowlist.validate("net.core.rmem_max", false, false).is_err());
+    }
+
+    #[test]
+    fn matches_prefix_wildcards() {
+        let allowlist = allowlist(&["net.core.*", "kernel.msg*"]);
+        assert!(allowlist.validate("net.core.rmem_max", false, false).is_ok());
+        assert!(allowlist.validate("kernel.msgmax", false, false).is_ok());
+        assert!(allowlist.validate("net.ipv6.conf.all.forwarding", false, false).is_err());
+    }
+
+    #[test]
+    fn classifies_namespaced_groups() {
+        assert_eq!(namespaced_by("kernel.sem"), Some(Namespace::Ipc));
+        assert_eq!(namespaced_by("kernel.shmmax"), Some(Namespace::Ipc));
+        assert_eq!(namespaced_by("kernel.msgmnb"), Some(Namespace::Ipc));
+        assert_eq!(namespaced_by("fs.mqueue.msg_max"), Some(Namespace::Ipc));
+        assert_eq!(namespaced_by("net.ipv4.tcp_keepalive_time"), Some(Namespace::Net));
+        assert_eq!(namespaced_by("kernel.semx"), None);
+        assert_eq!(namespaced_by("vm.swappiness"), None);
+    }
+
+    #[test]
+    fn rejects_patterns_that_are_not_namespaced() {
+        assert!(PatternAllowlist::new(&["vm.swappiness".to_string()]).is_err());
+        assert!(PatternAllowlist::new(&["kernel.*".to_string()]).is_err());
+    }
+
+    #[test]
+    fn rejects_namespaced_sysctls_shared_with_the_host() {
+        let allowlist = allowlist(&["kernel.shm*"]);
+        assert!(allowlist.validate("net.ipv4.tcp_syncookies", true, false).is_err());
+        assert!(allowlist.validate("net.ipv4.tcp_syncookies", false, true).is_ok());
+        assert!(allowlist.validate("kernel.shmmax", false, true).is_err());
+        assert!(allowlist.admit(&pod(&["kernel.shmmax", "net.ipv4.tcp_syncookies"], false)).is_ok());
+        assert!(allowlist.admit(&pod(&["net.ipv4.tcp_syncookies"], true)).is_err());
+    }
+}
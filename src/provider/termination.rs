@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use k8s_openapi::api::core::v1::{Container, Pod};
+
+use crate::kubelet::config::config;
+use crate::logs::reader::{self, LogOptions};
+use crate::provider::cri;
+
+const DEFAULT_MESSAGE_PATH: &str = "/dev/termination-log";
+const MESSAGE_FILE: &str = "termination-log";
+const MAX_CONTAINER_MESSAGE_LEN: usize = 4 * 1024;
+const MAX_POD_MESSAGE_LEN: usize = 12 * 1024;
+const FALLBACK_LOG_LINES: usize = 80;
+
+/// Messages of exited containers, keyed by pod UID and container ID. An
+/// exited container's message no longer changes, so it is read only once.
+static MESSAGES: Mutex<BTreeMap<String, BTreeMap<String, String>>> = Mutex::new(BTreeMap::new());
+
+fn message_file(pod_uid: &str, container_name: &str) -> PathBuf {
+    config().pod_dir(pod_uid).join("containers").join(container_name).join(MESSAGE_FILE)
+}
+
+/// Creates the host file backing `terminationMessagePath` and returns its mount.
+pub async fn termination_message_mount(pod: &Pod, container: &Container) -> anyhow::Result<Option<cri::Mount>> {
+    let container_path = container.termination_message_path.clone().unwrap_or_else(|| DEFAULT_MESSAGE_PATH.to_string());
+    if container_path.is_empty() {
+        return Ok(None);
+    }
+    let path = message_file(&pod.metadata.uid.clone().unwrap_or_default(), &container.name);
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    tokio::fs::write(&path, b"").await?;
+    // The container may run as any user and must be able to write it.
+    tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)).await?;
+    Ok(Some(cri::Mount {
+        container_path,
+        host_path: path.to_string_lossy().into_owned(),
+        readonly: false,
+        selinux_relabel: true,
+        propagation: cri::MountPropagation::PropagationPrivate as i32,
+    }))
+}
+
+/// Each container gets an equal share of the pod budget, up to the
+/// per-container cap.
+pub fn message_limit(container_count: usize) -> usize {
+    (MAX_POD_MESSAGE_LEN / container_count.max(1)).min(MAX_CONTAINER_MESSAGE_LEN)
+}
+
+/// The message of an exited container: the termination message file, or the
+/// tail of its log under `FallbackToLogsOnError` when the file is empty and
+/// the container failed.
+pub async fn termination_message(pod: &Pod, container: &Container, status: &cri::ContainerStatus, limit: usize) -> String {
+    let pod_uid = pod.metadata.uid.clone().unwrap_or_default();
+    if let Some(message) = MESSAGES.lock().unwrap().get(&pod_uid).and_then(|m| m.get(&status.id)) {
+        return message.clone();
+    }
+    let message = read_message(&message_file(&pod_uid, &container.name), container, status, limit).await;
+    MESSAGES.lock().unwrap().entry(pod_uid).or_default().insert(status.id.clone(), message.clone());
+    message
+}
+
+/// Drops the cached messages of a removed pod.
+pub fn forget(pod_uid: &str) {
+    MESSAGES.lock().unwrap().remove(pod_uid);
+}
+
+async fn read_message(path: &Path, container: &Container, status: &cri::ContainerStatus, limit: usize) -> String {
+    let mut message = tokio::fs::read(path).await.unwrap_or_default();
+    message.truncate(limit);
+
+    let fallback = container.termination_message_policy.as_deref() == Some("FallbackToLogsOnError");
+    if message.is_empty() && fallback && status.exit_code != 0 {
+        message = log_tail(status, limit).await;
+    }
+    if message.is_empty() {
+        return status.message.clone();
+    }
+    String::from_utf8_lossy(&message).into_owned()
+}
+
+async fn log_tail(status: &cri::ContainerStatus, limit: usize) -> Vec<u8> {
+    if status.log_path.is_empty() {
+        return vec![];
+    }
+    let options = LogOptions { tail_lines: Some(FALLBACK_LOG_LINES), ..Default::default() };
+    let mut tail = vec![];
+    let _ = reader::read_logs(Path::new(&status.log_path), &status.id, &options, &mut tail).await;
+    // Keep the end of the log, where the failure is most likely reported.
+    tail.split_off(tail.len().saturating_sub(limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_pod_budget_between_containers() {
+        assert_eq!(message_limit(0), MAX_CONTAINER_MESSAGE_LEN);
+        assert_eq!(message_limit(1), MAX_CONTAINER_MESSAGE_LEN);
+        assert_eq!(message_limit(3), MAX_CONTAINER_MESSAGE_LEN);
+        assert_eq!(message_limit(4), 3 * 1024);
+        assert_eq!(message_limit(12), 1024);
+        assert!((1..=100).all(|n| n * message_limit(n) <= MAX_POD_MESSAGE_LEN));
+    }
+
+    fn container(policy: &str) -> Container {
+        Container {
+            name: "app".to_string(),
+            termination_message_policy: Some(policy.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn exited(exit_code: i32, log_path: &Path) -> cri::ContainerStatus {
+        cri::ContainerStatus {
+            id: "abc".to_string(),
+            exit_code,
+            message: "runtime message".to_string(),
+            log_path: log_path.to_string_lossy().into_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_logs_only_on_failure_with_an_empty_file() {
+        let dir = std::env::temp_dir().join(format!("rust-kubelet-termination-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let file = dir.join(MESSAGE_FILE);
+        let log = dir.join("0.log");
+        tokio::fs::write(&log, "2016-10-06T00:17:09Z stdout F starting\n2016-10-06T00:17:10Z stderr F panic: boom\n")
+            .await
+            .unwrap();
+        let fallback = container("FallbackToLogsOnError");
+
+        tokio::fs::write(&file, "").await.unwrap();
+        assert_eq!(read_message(&file, &fallback, &exited(1, &log), 4096).await, "starting\npanic: boom\n");
+        // The end of the log is kept when it exceeds the limit.
+        assert_eq!(read_message(&file, &fallback, &exited(1, &log), 5).await, "boom\n");
+        assert_eq!(read_message(&file, &fallback, &exited(0, &log), 4096).await, "runtime message");
+        assert_eq!(read_message(&file, &container("File"), &exited(1, &log), 4096).await, "runtime message");
+
+        tokio::fs::write(&file, "custom message").await.unwrap();
+        assert_eq!(read_message(&file, &fallback, &exited(1, &log), 4096).await, "custom message");
+        assert_eq!(read_message(&file, &fallback, &exited(1, &log), 6).await, "custom");
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+}
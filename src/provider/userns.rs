@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use k8s_openapi::api::core::v1::Pod;
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::provider::cri;
+use crate::provider::cri::NamespaceMode;
+
+/// Every user-namespaced pod gets this many UIDs and GIDs.
+pub const USERNS_LENGTH: u32 = 65536;
+pub const EXHAUSTED_REASON: &str = "UserNamespaceAllocationFailed";
+
+const USERNS_FILE: &str = "userns";
+
+static MANAGER: OnceLock<Mutex<UsernsManager>> = OnceLock::new();
+
+/// On-disk form of a pod's mappings, kept in `<rootDir>/pods/<uid>/userns`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserNamespaceMappings {
+    uid_mappings: Vec<IdMapping>,
+    gid_mappings: Vec<IdMapping>,
+}
+
+impl UserNamespaceMappings {
+    /// The same range for UIDs and GIDs, starting at `host_id`.
+    fn for_host_id(host_id: u32) -> Self {
+        let mapping = IdMapping { host_id, container_id: 0, length: USERNS_LENGTH };
+        UserNamespaceMappings { uid_mappings: vec![mapping.clone()], gid_mappings: vec![mapping] }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IdMapping {
+    host_id: u32,
+    container_id: u32,
+    length: u32,
+}
+
+/// Hands out non-overlapping host ID ranges from the configured pool.
+struct UsernsManager {
+    first_id: u32,
+    blocks: u32,
+    allocated: BTreeMap<u32, String>,
+}
+
+impl UsernsManager {
+    fn new(first_id: u32, length: u32) -> Self {
+        UsernsManager { first_id, blocks: length / USERNS_LENGTH, allocated: BTreeMap::new() }
+    }
+
+    /// Takes back the ranges recorded in `<pods_dir>/<uid>/userns`.
+    fn restore(&mut self, pods_dir: &Path) {
+        for entry in std::fs::read_dir(pods_dir).into_iter().flatten().flatten() {
+            let pod_uid = entry.file_name().to_string_lossy().into_owned();
+            let Ok(content) = std::fs::read_to_string(entry.path().join(USERNS_FILE)) else {
+                continue;
+            };
+            let restored = serde_json::from_str::<UserNamespaceMappings>(&content)
+                .ok()
+                .and_then(|m| m.uid_mappings.first().and_then(|m| self.block_of(m.host_id)));
+            match restored {
+                Some(block) if !self.allocated.contains_key(&block) => {
+                    self.allocated.insert(block, pod_uid);
+                }
+                _ => warn!("ignoring invalid user namespace allocation for pod {}", pod_uid),
+            }
+        }
+    }
+
+    fn block_of(&self, host_id: u32) -> Option<u32> {
+        if host_id < self.first_id || !(host_id - self.first_id).is_multiple_of(USERNS_LENGTH) {
+            return None;
+        }
+        Some((host_id - self.first_id) / USERNS_LENGTH).filter(|b| *b < self.blocks)
+    }
+
+    fn find(&self, pod_uid: &str) -> Option<u32> {
+        self.allocated.iter().find(|(_, uid)| uid.as_str() == pod_uid).map(|(block, _)| *block)
+    }
+
+    fn allocate(&mut self, pod_uid: &str) -> Option<u32> {
+        if let Some(block) = self.find(pod_uid) {
+            return Some(block);
+        }
+        let block = (0..self.blocks).find(|b| !self.allocated.contains_key(b))?;
+        self.allocated.insert(block, pod_uid.to_string());
+        Some(block)
+    }
+
+    fn release(&mut self, pod_uid: &str) {
+        self.allocated.retain(|_, uid| uid != pod_uid);
+    }
+
+    fn host_id(&self, block: u32) -> u32 {
+        self.first_id + block * USERNS_LENGTH
+    }
+}
+
+fn userns_file(pod_uid: &str) -> PathBuf {
+    config().pod_dir(pod_uid).join(USERNS_FILE)
+}
+
+/// Restores the allocations recorded under the pods directory so that pods
+/// keep their ranges across kubelet restarts.
+pub fn init() -> anyhow::Result<()> {
+    let pool = &config().user_namespaces;
+    if !pool.first_id.is_multiple_of(USERNS_LENGTH) || !pool.length.is_multiple_of(USERNS_LENGTH) {
+        anyhow::bail!("user namespace pool must be aligned to {} IDs", USERNS_LENGTH);
+    }
+    if pool.first_id.checked_add(pool.length).is_none() {
+        anyhow::bail!("user namespace pool exceeds the 32-bit ID space");
+    }
+    let mut manager = UsernsManager::new(pool.first_id, pool.length);
+    manager.restore(&PathBuf::from(&config().root_dir).join("pods"));
+    info!("restored {} user namespace allocations", manager.allocated.len());
+    let _ = MANAGER.set(Mutex::new(manager));
+    Ok(())
+}
+
+fn manager() -> &'static Mutex<UsernsManager> {
+    MANAGER.get().expect("user namespace manager not initialised")
+}
+
+fn wants_user_namespace(pod: &Pod) -> bool {
+    pod.spec.as_ref().and_then(|s| s.host_users) == Some(false)
+}
+
+/// Reserves a range for pods with `hostUsers: false` and records it on disk.
+pub fn allocate(pod: &Pod) -> anyhow::Result<()> {
+    if !wants_user_namespace(pod) {
+        return Ok(());
+    }
+    let pod_uid = pod.metadata.uid.clone().unwrap_or_default();
+    let mut manager = manager().lock().unwrap();
+    let block = manager
+        .allocate(&pod_uid)
+        .ok_or_else(|| anyhow::anyhow!("no free user namespace range left in the pool"))?;
+    let mappings = UserNamespaceMappings::for_host_id(manager.host_id(block));
+    let path = userns_file(&pod_uid);
+    let persisted = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(&path, serde_json::to_vec(&mappings).unwrap()));
+    if let Err(e) = persisted {
+        manager.release(&pod_uid);
+        anyhow::bail!("Unable to record user namespace for pod {}: {}", pod_uid, e);
+    }
+    Ok(())
+}
+
+/// Frees the pod's range once the pod is gone.
+pub fn release(pod_uid: &str) {
+    manager().lock().unwrap().release(pod_uid);
+    let _ = std::fs::remove_file(userns_file(pod_uid));
+}
+
+/// The CRI user namespace for the pod: the host's for regular pods, the
+/// allocated range for pods with `hostUsers: false`.
+pub fn user_namespace_for_pod(pod: &Pod) -> cri::UserNamespace {
+    let pod_uid = pod.metadata.uid.clone().unwrap_or_default();
+    let manager = manager().lock().unwrap();
+    let block = manager.find(&pod_uid).filter(|_| wants_user_namespace(pod));
+    match block {
+        Some(block) => {
+            let mapping = cri::IdMapping { host_id: manager.host_id(block), container_id: 0, length: USERNS_LENGTH };
+            cri::UserNamespace {
+                mode: NamespaceMode::Pod as i32,
+                uids: vec![mapping.clone()],
+                gids: vec![mapping],
+            }
+        }
+        None => cri::UserNamespace { mode: NamespaceMode::Node as i32, ..Default::default() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST_ID: u32 = USERNS_LENGTH;
+
+    fn manager(blocks: u32) -> UsernsManager {
+        UsernsManager::new(FIRST_ID, blocks * USERNS_LENGTH)
+    }
+
+    #[test]
+    fn allocates_non_overlapping_ranges() {
+        let mut manager = manager(4);
+        let blocks: Vec<u32> = ["a", "b", "c", "d"].iter().map(|uid| manager.allocate(uid).unwrap()).collect();
+        let mut starts: Vec<u32> = blocks.iter().map(|block| manager.host_id(*block)).collect();
+        starts.sort();
+        for pair in starts.windows(2) {
+            assert!(pair[0] + USERNS_LENGTH <= pair[1], "{:?} overlap", pair);
+        }
+        assert!(starts.iter().all(|start| *start >= FIRST_ID && start + USERNS_LENGTH <= FIRST_ID + 4 * USERNS_LENGTH));
+        // A pod asking again keeps its range.
+        assert_eq!(manager.allocate("a"), manager.find("a"));
+    }
+
+    #[test]
+    fn rejects_allocation_when_pool_is_exhausted() {
+        let mut manager = manager(2);
+        assert!(manager.allocate("a").is_some());
+        assert!(manager.allocate("b").is_some());
+        assert_eq!(manager.allocate("c"), None);
+    }
+
+    #[test]
+    fn reuses_released_blocks() {
+        let mut manager = manager(2);
+        let first = manager.allocate("a").unwrap();
+        manager.allocate("b").unwrap();
+        manager.release("a");
+        assert_eq!(manager.find("a"), None);
+        assert_eq!(manager.allocate("c"), Some(first));
+    }
+
+    #[test]
+    fn restores_persisted_allocations() {
+        let pods_dir = std::env::temp_dir().join(format!("rust-kubelet-userns-{}", std::process::id()));
+        let mut manager = manager(4);
+        for uid in ["a", "b"] {
+            let block = manager.allocate(uid).unwrap();
+            let mappings = UserNamespaceMappings::for_host_id(manager.host_id(block));
+            std::fs::create_dir_all(pods_dir.join(uid)).unwrap();
+            std::fs::write(pods_dir.join(uid).join(USERNS_FILE), serde_json::to_vec(&mappings).unwrap()).unwrap();
+        }
+        // Misaligned ranges are not taken back.
+        let misaligned = UserNamespaceMappings::for_host_id(FIRST_ID + 1);
+        std::fs::create_dir_all(pods_dir.join("bad")).unwrap();
+        std::fs::write(pods_dir.join("bad").join(USERNS_FILE), serde_json::to_vec(&misaligned).unwrap()).unwrap();
+
+        let mut reloaded = self::manager(4);
+        reloaded.restore(&pods_dir);
+        let _ = std::fs::remove_dir_all(&pods_dir);
+        assert_eq!(reloaded.find("a"), manager.find("a"));
+        assert_eq!(reloaded.find("b"), manager.find("b"));
+        assert_eq!(reloaded.find("bad"), None);
+        let next = reloaded.allocate("c").unwrap();
+        assert!(next != manager.find("a").unwrap() && next != manager.find("b").unwrap());
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::{header, Method, Request, StatusCode};
+use axum::middleware::{AddExtension, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::RustlsAcceptor;
+use k8s_openapi::api::authentication::v1::{TokenReview, TokenReviewSpec};
+use k8s_openapi::api::authorization::v1::{ResourceAttributes, SubjectAccessReview, SubjectAccessReviewSpec};
+use kube::api::PostParams;
+use kube::Api;
+use rustls::Certificate;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+use tracing::*;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::kubelet::client;
+use crate::kubelet::config::{config, parse_duration, AuthorizationMode};
+use crate::kubelet::minikubelet::NODE_NAME;
+
+const ANONYMOUS_USER: &str = "system:anonymous";
+const UNAUTHENTICATED_GROUP: &str = "system:unauthenticated";
+const AUTHENTICATED_GROUP: &str = "system:authenticated";
+
+/// Identity a request was authenticated as.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct User {
+    pub name: String,
+    pub uid: String,
+    pub groups: Vec<String>,
+    pub extra: BTreeMap<String, Vec<String>>,
+}
+
+impl User {
+    fn anonymous() -> Self {
+        User { name: ANONYMOUS_USER.to_string(), groups: vec![UNAUTHENTICATED_GROUP.to_string()], ..Default::default() }
+    }
+
+    fn authenticated(mut self) -> Self {
+        if !self.groups.iter().any(|g| g == AUTHENTICATED_GROUP) {
+            self.groups.push(AUTHENTICATED_GROUP.to_string());
+        }
+        self
+    }
+}
+
+/// The user named by a connection's verified client certificate, if any.
+#[derive(Clone, Debug)]
+pub struct ClientCertUser(Option<User>);
+
+/// Completes the TLS handshake and attaches the client certificate's
+/// identity to every request on the connection.
+#[derive(Clone)]
+pub struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub fn new(inner: RustlsAcceptor) -> Self {
+        ClientCertAcceptor { inner }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, ClientCertUser>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let handshake = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = handshake.await?;
+            // rustls has already verified the chain against the client CA.
+            let user = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()).and_then(cert_user);
+            Ok((stream, Extension(ClientCertUser(user)).layer(service)))
+        })
+    }
+}
+
+/// CN is the user name and each O a group, as for the API server.
+fn cert_user(cert: &Certificate) -> Option<User> {
+    let (_, cert) = X509Certificate::from_der(&cert.0).ok()?;
+    let subject = cert.subject();
+    let name = subject.iter_common_name().next()?.as_str().ok()?.to_string();
+    let groups = subject.iter_organization().filter_map(|o| o.as_str().ok()).map(str::to_string).collect();
+    Some(User { name, groups, ..Default::default() })
+}
+
+/// A map whose entries expire after a per-entry TTL.
+struct TtlCache<K, V> {
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    fn new() -> Self {
+        TtlCache { entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key).filter(|(expires, _)| *expires > Instant::now()).map(|(_, value)| value.clone())
+    }
+
+    fn insert(&self, key: K, value: V, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, (expires, _)| *expires > now);
+        entries.insert(key, (now + ttl, value));
+    }
+}
+
+/// The access being checked: `verb` on the `nodes/<subresource>` of this node.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Attributes {
+    user: User,
+    verb: String,
+    subresource: &'static str,
+}
+
+/// Authenticates and authorizes kubelet API requests against the API server.
+/// Reviews go through a fresh client so they use the current certificate.
+pub struct Auth {
+    token_ttl: Duration,
+    authorized_ttl: Duration,
+    unauthorized_ttl: Duration,
+    tokens: TtlCache<String, Option<User>>,
+    decisions: TtlCache<Attributes, bool>,
+}
+
+impl Auth {
+    pub fn new() -> anyhow::Result<Self> {
+        let config = config();
+        Ok(Auth {
+            token_ttl: parse_duration(&config.authentication.webhook.cache_ttl)?,
+            authorized_ttl: parse_duration(&config.authorization.webhook.cache_authorized_ttl)?,
+            unauthorized_ttl: parse_duration(&config.authorization.webhook.cache_unauthorized_ttl)?,
+            tokens: TtlCache::new(),
+            decisions: TtlCache::new(),
+        })
+    }
+
+    /// Client certificate, then bearer token, then anonymous. A presented
+    /// credential that fails does not fall back to anonymous.
+    async fn authenticate<B>(&self, request: &Request<B>) -> Option<User> {
+        if let Some(ClientCertUser(Some(user))) = request.extensions().get::<ClientCertUser>() {
+            return Some(user.clone().authenticated());
+        }
+        if let Some(token) = bearer_token(request) {
+            if !config().authentication.webhook.enabled {
+                return None;
+            }
+            return self.review_token(token).await.map(User::authenticated);
+        }
+        config().authentication.anonymous.enabled.then(User::anonymous)
+    }
+
+    async fn review_token(&self, token: &str) -> Option<User> {
+        if let Some(user) = self.tokens.get(&token.to_string()) {
+            return user;
+        }
+        let review = TokenReview {
+            spec: TokenReviewSpec { token: Some(token.to_string()), audiences: None },
+            ..Default::default()
+        };
+        let created = match client::client().await {
+            Ok(client) => Api::<TokenReview>::all(client).create(&PostParams::default(), &review).await.map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        let status = match created {
+            Ok(review) => review.status.unwrap_or_default(),
+            Err(e) => {
+                // Transient failures are not cached.
+                warn!("TokenReview failed: {}", e);
+                return None;
+            }
+        };
+        let user = match (status.authenticated, status.user) {
+            (Some(true), Some(info)) => Some(User {
+                name: info.username.unwrap_or_default(),
+                uid: info.uid.unwrap_or_default(),
+                groups: info.groups.unwrap_or_default(),
+                extra: info.extra.unwrap_or_default(),
+            }),
+            _ => {
+                debug!("token rejected: {}", status.error.unwrap_or_default());
+                None
+            }
+        };
+        self.tokens.insert(token.to_string(), user.clone(), self.token_ttl);
+        user
+    }
+
+    async fn authorize(&self, attributes: &Attributes) -> anyhow::Result<bool> {
+        if config().authorization.mode == AuthorizationMode::AlwaysAllow {
+            return Ok(true);
+        }
+        if let Some(allowed) = self.decisions.get(attributes) {
+            return Ok(allowed);
+        }
+        let user = &attributes.user;
+        let review = SubjectAccessReview {
+            spec: SubjectAccessReviewSpec {
+                user: Some(user.name.clone()),
+                uid: Some(user.uid.clone()),
+                groups: Some(user.groups.clone()),
+                extra: Some(user.extra.clone()),
+                resource_attributes: Some(ResourceAttributes {
+                    verb: Some(attributes.verb.clone()),
+                    group: Some(String::new()),
+                    version: Some("v1".to_string()),
+                    resource: Some("nodes".to_string()),
+                    subresource: Some(attributes.subresource.to_string()),
+                    name: Some(NODE_NAME.to_string()),
+                    ..Default::default()
+                }),
+                non_resource_attributes: None,
+            },
+            ..Default::default()
+        };
+        let review = Api::<SubjectAccessReview>::all(client::client().await?)
+            .create(&PostParams::default(), &review)
+            .await?;
+        let allowed = review.status.is_some_and(|s| s.allowed);
+        let ttl = if allowed { self.authorized_ttl } else { self.unauthorized_ttl };
+        self.decisions.insert(attributes.clone(), allowed, ttl);
+        Ok(allowed)
+    }
+}
+
+fn bearer_token<B>(request: &Request<B>) -> Option<&str> {
+    let value = request.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+fn verb(method: &Method) -> String {
+    match *method {
+        Method::GET | Method::HEAD => "get".to_string(),
+        Method::POST => "create".to_string(),
+        Method::PUT => "update".to_string(),
+        Method::PATCH => "patch".to_string(),
+        Method::DELETE => "delete".to_string(),
+        _ => method.as_str().to_lowercase(),
+    }
+}
+
+/// The `nodes` subresource guarding a path; anything unlisted is `proxy`.
+fn subresource(path: &str) -> &'static str {
+    let under = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+    if under("/stats") {
+        "stats"
+    } else if under("/metrics") {
+        "metrics"
+    } else if under("/logs") {
+        "log"
+    } else if under("/spec") {
+        "spec"
+    } else {
+        "proxy"
+    }
+}
+
+/// Middleware rejecting unauthenticated (401) and unauthorized (403) requests.
+pub async fn filter<B>(State(auth): State<Arc<Auth>>, request: Request<B>, next: Next<B>) -> Response {
+    let Some(user) = auth.authenticate(&request).await else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+    let attributes = Attributes {
+        user,
+        verb: verb(request.method()),
+        subresource: subresource(request.uri().path()),
+    };
+    match auth.authorize(&attributes).await {
+        Ok(true) => next.run(request).await,
+        Ok(false) => {
+            let message = format!(
+                "Forbidden (user={}, verb={}, resource=nodes, subresource={})",
+                attributes.user.name, attributes.verb, attributes.subresource
+            );
+            info!("{}", message);
+            (StatusCode::FORBIDDEN, message).into_response()
+        }
+        Err(e) => {
+            error!("SubjectAccessReview failed for {}: {}", attributes.user.name, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Authorization error").into_response()
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use axum::Json;
+use serde_json::Value;
+
+use crate::kubelet::config::config;
+
+/// `GET /configz`: the effective kubelet configuration.
+pub async fn configz() -> Json<Value> {
+    let mut kubelet_config = serde_json::to_value(config()).unwrap_or_default();
+    if let Value::Object(fields) = &mut kubelet_config {
+        fields.insert("kind".to_string(), "KubeletConfiguration".into());
+        fields.insert("apiVersion".to_string(), "kubelet.config.k8s.io/v1beta1".into());
+    }
+    Json(serde_json::json!({ "kubeletconfig": kubelet_config }))
+}
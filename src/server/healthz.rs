@@ -0,0 +1,50 @@
+use axum::extract::{Path, RawQuery};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::kubelet::health;
+
+type Check = fn() -> Result<(), String>;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("ping", || Ok(())),
+    ("syncloop", health::sync_loop_healthy),
+    ("pleg", health::pleg_healthy),
+];
+
+/// `GET /healthz`; `?verbose` lists every check.
+pub async fn healthz(RawQuery(query): RawQuery) -> Response {
+    let verbose = query.is_some_and(|q| q.split('&').any(|p| p == "verbose" || p.starts_with("verbose=")));
+    let mut failed = false;
+    let mut report = String::new();
+    for (name, check) in CHECKS {
+        match check() {
+            Ok(()) => report.push_str(&format!("[+]{} ok\n", name)),
+            Err(e) => {
+                tracing::warn!("healthz check {} failed: {}", name, e);
+                failed = true;
+                report.push_str(&format!("[-]{} failed: reason withheld\n", name));
+            }
+        }
+    }
+    if failed {
+        report.push_str("healthz check failed\n");
+        return (StatusCode::INTERNAL_SERVER_ERROR, report).into_response();
+    }
+    if verbose {
+        report.push_str("healthz check passed\n");
+        return report.into_response();
+    }
+    "ok".into_response()
+}
+
+/// `GET /healthz/:check`
+pub async fn check(Path(name): Path<String>) -> Response {
+    match CHECKS.iter().find(|(n, _)| *n == name) {
+        Some((_, check)) => match check() {
+            Ok(()) => "ok".into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("internal server error: {}\n", e)).into_response(),
+        },
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use axum::body::StreamBody;
+use axum::extract::{Path, Query};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+use tracing::*;
+
+use crate::logs::reader::{self, LogOptions};
+use crate::provider::pod;
+
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Query parameters of `kubectl logs`, as sent by the API server.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogQuery {
+    follow: bool,
+    previous: bool,
+    tail_lines: Option<i64>,
+    since_seconds: Option<i64>,
+    since_time: Option<String>,
+    timestamps: bool,
+    limit_bytes: Option<i64>,
+}
+
+impl LogQuery {
+    fn options(&self) -> Result<LogOptions, String> {
+        if self.since_seconds.is_some() && self.since_time.is_some() {
+            return Err("at most one of sinceTime or sinceSeconds may be specified".to_string());
+        }
+        let since_time = match &self.since_time {
+            Some(t) => Some(
+                DateTime::parse_from_rfc3339(t)
+                    .map_err(|e| format!("invalid sinceTime {:?}: {}", t, e))?
+                    .with_timezone(&Utc),
+            ),
+            None => None,
+        };
+        if self.since_seconds.is_some_and(|s| s < 1) {
+            return Err("sinceSeconds must be greater than 0".to_string());
+        }
+        if self.tail_lines.is_some_and(|n| n < 0) {
+            return Err("tailLines must be greater than or equal to 0".to_string());
+        }
+        if self.limit_bytes.is_some_and(|n| n < 1) {
+            return Err("limitBytes must be greater than 0".to_string());
+        }
+        Ok(LogOptions {
+            follow: self.follow,
+            tail_lines: self.tail_lines.map(|n| n as usize),
+            since_seconds: self.since_seconds,
+            since_time,
+            limit_bytes: self.limit_bytes.map(|n| n as usize),
+            timestamps: self.timestamps,
+            ..Default::default()
+        })
+    }
+}
+
+/// `GET /containerLogs/{namespace}/{pod}/{container}`
+pub async fn container_logs(
+    Path((namespace, pod_name, container_name)): Path<(String, String, String)>,
+    Query(query): Query<LogQuery>,
+) -> Response {
+    let options = match query.options() {
+        Ok(options) => options,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let status = match pod::find_container(&namespace, &pod_name, &container_name, query.previous).await {
+        Ok(Some(status)) if !status.log_path.is_empty() => status,
+        Ok(_) => {
+            let which = if query.previous { "previous terminated container" } else { "container" };
+            return (
+                StatusCode::NOT_FOUND,
+                format!("{} {:?} in pod {:?} not found", which, container_name, pod_name),
+            )
+                .into_response();
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let (mut writer, body) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+    let log_path = PathBuf::from(status.log_path);
+    tokio::spawn(async move {
+        // Ends with an error once the client goes away and the pipe closes.
+        if let Err(e) = reader::read_logs(&log_path, &status.id, &options, &mut writer).await {
+            debug!("container log stream for {} ended: {}", status.id, e);
+        }
+    });
+    (
+        [(header::CONTENT_TYPE, "text/plain")],
+        StreamBody::new(ReaderStream::new(body)),
+    )
+        .into_response()
+}
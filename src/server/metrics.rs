@@ -0,0 +1,26 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use crate::metrics;
+
+fn text_response(metrics: anyhow::Result<String>) -> Response {
+    match metrics {
+        Ok(body) => ([(header::CONTENT_TYPE, metrics::TEXT_FORMAT)], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// `GET /metrics`
+pub async fn kubelet() -> Response {
+    text_response(metrics::kubelet::kubelet_metrics().await)
+}
+
+/// `GET /metrics/resource`
+pub async fn resource() -> Response {
+    text_response(metrics::resource::resource_metrics().await)
+}
+
+/// `GET /metrics/cadvisor`
+pub async fn cadvisor() -> Response {
+    text_response(metrics::cadvisor::cadvisor_metrics().await)
+}
@@ -0,0 +1,56 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::routing::get;
+use axum::{middleware, Router};
+use axum_server::tls_rustls::RustlsAcceptor;
+use tracing::*;
+
+use crate::kubelet::config::config;
+
+mod auth;
+mod configz;
+mod healthz;
+mod logs;
+mod metrics;
+mod pods;
+mod stats;
+mod streaming;
+mod tls;
+
+/// Serves the kubelet API over HTTPS on `address:port`.
+pub async fn serve(kube_config: kube::Config) -> anyhow::Result<()> {
+    let config = config();
+    let ip: IpAddr = config.address.parse()?;
+    let addr = SocketAddr::new(ip, config.port);
+    let tls = tls::rustls_config(&kube_config)?;
+    let auth = Arc::new(auth::Auth::new()?);
+    info!("kubelet API listening on {}", addr);
+    axum_server::bind(addr)
+        .acceptor(auth::ClientCertAcceptor::new(RustlsAcceptor::new(tls)))
+        .serve(router(auth).into_make_service())
+        .await?;
+    Ok(())
+}
+
+/// Kubelet API routes, all behind authentication and authorization.
+fn router(auth: Arc<auth::Auth>) -> Router {
+    Router::new()
+        .route("/containerLogs/:namespace/:pod/:container", get(logs::container_logs))
+        .route("/exec/:namespace/:pod/:container", get(streaming::exec).post(streaming::exec))
+        .route("/exec/:namespace/:pod/:uid/:container", get(streaming::exec).post(streaming::exec))
+        .route("/attach/:namespace/:pod/:container", get(streaming::attach).post(streaming::attach))
+        .route("/attach/:namespace/:pod/:uid/:container", get(streaming::attach).post(streaming::attach))
+        .route("/portForward/:namespace/:pod", get(streaming::port_forward).post(streaming::port_forward))
+        .route("/portForward/:namespace/:pod/:uid", get(streaming::port_forward).post(streaming::port_forward))
+        .route("/healthz", get(healthz::healthz))
+        .route("/healthz/:check", get(healthz::check))
+        .route("/pods", get(pods::pods))
+        .route("/runningpods", get(pods::running_pods))
+        .route("/configz", get(configz::configz))
+        .route("/stats/summary", get(stats::summary))
+        .route("/metrics", get(metrics::kubelet))
+        .route("/metrics/resource", get(metrics::resource))
+        .route("/metrics/cadvisor", get(metrics::cadvisor))
+        .layer(middleware::from_fn_with_state(auth, auth::filter))
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use k8s_openapi::api::core::v1::{Container, Pod, PodSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+use crate::provider::{cri, get_client, pod_manager};
+
+fn pod_list(pods: Vec<Pod>) -> Response {
+    Json(serde_json::json!({
+        "kind": "PodList",
+        "apiVersion": "v1",
+        "metadata": {},
+        "items": pods,
+    }))
+    .into_response()
+}
+
+/// `GET /pods`: the pods this kubelet has admitted.
+pub async fn pods() -> Response {
+    pod_list(pod_manager::pods())
+}
+
+/// `GET /runningpods`: pods as the runtime sees them, with only the fields
+/// the runtime knows about.
+pub async fn running_pods() -> Response {
+    match running().await {
+        Ok(pods) => pod_list(pods),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("failed to list running pods: {}", e)).into_response(),
+    }
+}
+
+async fn running() -> anyhow::Result<Vec<Pod>> {
+    let filter = cri::PodSandboxFilter {
+        state: Some(cri::PodSandboxStateValue { state: cri::PodSandboxState::SandboxReady as i32 }),
+        ..Default::default()
+    };
+    let sandboxes = get_client().await
+        .list_pod_sandbox(cri::ListPodSandboxRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .items;
+    let filter = cri::ContainerFilter {
+        state: Some(cri::ContainerStateValue { state: cri::ContainerState::ContainerRunning as i32 }),
+        ..Default::default()
+    };
+    let containers = get_client().await
+        .list_containers(cri::ListContainersRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .containers;
+
+    // Keyed by UID, so a pod whose sandbox was recreated is listed once.
+    let mut pods: BTreeMap<String, Pod> = BTreeMap::new();
+    for sandbox in sandboxes {
+        let Some(metadata) = sandbox.metadata else { continue };
+        let pod = pods.entry(metadata.uid.clone()).or_insert_with(|| Pod {
+            metadata: ObjectMeta {
+                name: Some(metadata.name),
+                namespace: Some(metadata.namespace),
+                uid: Some(metadata.uid),
+                ..Default::default()
+            },
+            spec: Some(PodSpec::default()),
+            ..Default::default()
+        });
+        let spec = pod.spec.as_mut().unwrap();
+        for container in containers.iter().filter(|c| c.pod_sandbox_id == sandbox.id) {
+            spec.containers.push(Container {
+                name: container.metadata.as_ref().map(|m| m.name.clone()).unwrap_or_default(),
+                image: container.image.as_ref().map(|i| i.image.clone()),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(pods.into_values().collect())
+}
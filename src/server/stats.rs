@@ -0,0 +1,21 @@
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+
+use crate::stats;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SummaryQuery {
+    only_cpu_and_memory: bool,
+}
+
+/// `GET /stats/summary`
+pub async fn summary(Query(query): Query<SummaryQuery>) -> Response {
+    match stats::summary(query.only_cpu_and_memory).await {
+        Ok(summary) => Json(summary).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("failed to get node info: {}", e)).into_response(),
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use axum::body::{self, Body};
+use axum::extract::Path;
+use axum::http::{header, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::provider::{cri, get_client, pod, pod_manager, RUNTIME_ENDPOINT};
+
+/// Options of an exec, attach or port-forward request. The API server sends
+/// `input`, `output`, `error` and `tty` set to `1`; the `stdin`, `stdout` and
+/// `stderr` spellings and `true` are accepted as well.
+#[derive(Debug, Default)]
+struct StreamOptions {
+    command: Vec<String>,
+    stdin: bool,
+    stdout: bool,
+    stderr: bool,
+    tty: bool,
+    ports: Vec<i32>,
+}
+
+impl StreamOptions {
+    fn from_query(query: Option<&str>) -> Result<Self, String> {
+        let mut options = StreamOptions::default();
+        for (key, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            let enabled = value == "1" || value == "true";
+            match key.as_ref() {
+                "command" => options.command.push(value.into_owned()),
+                "input" | "stdin" => options.stdin = enabled,
+                "output" | "stdout" => options.stdout = enabled,
+                "error" | "stderr" => options.stderr = enabled,
+                "tty" => options.tty = enabled,
+                "port" => options.ports.push(value.parse().map_err(|_| format!("invalid port {:?}", value))?),
+                _ => {}
+            }
+        }
+        // A terminal merges stderr into stdout.
+        if options.tty {
+            options.stderr = false;
+        }
+        Ok(options)
+    }
+
+    fn validate_streams(&self) -> Result<(), String> {
+        if !(self.stdin || self.stdout || self.stderr) {
+            return Err("you must specify at least 1 of stdin, stdout, stderr".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn error_response(status: StatusCode, message: impl ToString) -> Response {
+    (status, message.to_string()).into_response()
+}
+
+/// With a `{uid}` in the path, the pod known by that name must have that UID,
+/// so a request meant for a deleted pod cannot reach its same-named successor.
+/// Returns the 404 to send otherwise.
+fn uid_mismatch(params: &HashMap<String, String>) -> Option<Response> {
+    let uid = params.get("uid")?;
+    let (namespace, pod_name) = (&params["namespace"], &params["pod"]);
+    let resolved = pod_manager::pods()
+        .into_iter()
+        .find(|p| p.metadata.namespace.as_ref() == Some(namespace) && p.metadata.name.as_ref() == Some(pod_name))
+        .and_then(|p| p.metadata.uid);
+    (resolved.as_ref() != Some(uid)).then(|| {
+        error_response(StatusCode::NOT_FOUND, format!("pod {}/{} with uid {} not found", namespace, pod_name, uid))
+    })
+}
+
+async fn container_id(params: &HashMap<String, String>) -> Result<String, Response> {
+    if let Some(response) = uid_mismatch(params) {
+        return Err(response);
+    }
+    let (namespace, pod_name, container_name) = (&params["namespace"], &params["pod"], &params["container"]);
+    match pod::find_container(namespace, pod_name, container_name, false).await {
+        Ok(Some(status)) => Ok(status.id),
+        Ok(None) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("container {:?} in pod {}/{} not found", container_name, namespace, pod_name),
+        )),
+        Err(e) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// `/exec/{namespace}/{pod}[/{uid}]/{container}`
+pub async fn exec(Path(params): Path<HashMap<String, String>>, request: Request<Body>) -> Response {
+    let options = match StreamOptions::from_query(request.uri().query()) {
+        Ok(options) => options,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    if options.command.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "you must specify at least one command for the container");
+    }
+    if let Err(e) = options.validate_streams() {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+    let container_id = match container_id(&params).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let exec = cri::ExecRequest {
+        container_id,
+        cmd: options.command,
+        tty: options.tty,
+        stdin: options.stdin,
+        stdout: options.stdout,
+        stderr: options.stderr,
+    };
+    match get_client().await.exec(exec).await {
+        Ok(response) => stream(&response.into_inner().url, request).await,
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.message()),
+    }
+}
+
+/// `/attach/{namespace}/{pod}[/{uid}]/{container}`
+pub async fn attach(Path(params): Path<HashMap<String, String>>, request: Request<Body>) -> Response {
+    let options = match StreamOptions::from_query(request.uri().query()) {
+        Ok(options) => options,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    if let Err(e) = options.validate_streams() {
+        return error_response(StatusCode::BAD_REQUEST, e);
+    }
+    let container_id = match container_id(&params).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let attach = cri::AttachRequest {
+        container_id,
+        stdin: options.stdin,
+        tty: options.tty,
+        stdout: options.stdout,
+        stderr: options.stderr,
+    };
+    match get_client().await.attach(attach).await {
+        Ok(response) => stream(&response.into_inner().url, request).await,
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.message()),
+    }
+}
+
+/// `/portForward/{namespace}/{pod}[/{uid}]`. SPDY clients pick ports per
+/// stream; WebSocket clients list them as `port` query parameters.
+pub async fn port_forward(Path(params): Path<HashMap<String, String>>, request: Request<Body>) -> Response {
+    let options = match StreamOptions::from_query(request.uri().query()) {
+        Ok(options) => options,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    if let Some(response) = uid_mismatch(&params) {
+        return response;
+    }
+    let (namespace, pod_name) = (&params["namespace"], &params["pod"]);
+    let pod_sandbox_id = match pod::find_sandbox(namespace, pod_name).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, format!("pod {}/{} not found", namespace, pod_name)),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let port_forward = cri::PortForwardRequest { pod_sandbox_id, port: options.ports };
+    match get_client().await.port_forward(port_forward).await {
+        Ok(response) => stream(&response.into_inner().url, request).await,
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.message()),
+    }
+}
+
+/// Streaming URLs may be relative to the runtime endpoint.
+fn resolve_url(url: &str) -> anyhow::Result<Uri> {
+    let uri: Uri = url.parse()?;
+    if uri.scheme().is_some() {
+        return Ok(uri);
+    }
+    Ok(format!("{}{}", RUNTIME_ENDPOINT.trim_end_matches('/'), url).parse()?)
+}
+
+/// Hands the client over to the runtime's streaming server. The SPDY or
+/// WebSocket protocol, stdin and terminal resizing are negotiated end to end,
+/// so the kubelet only has to relay the upgraded connection.
+async fn stream(url: &str, request: Request<Body>) -> Response {
+    let target = match resolve_url(url) {
+        Ok(target) => target,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("invalid streaming URL {:?}: {}", url, e)),
+    };
+    if config().redirect_container_streaming {
+        return (StatusCode::FOUND, [(header::LOCATION, target.to_string())]).into_response();
+    }
+    match proxy(target, request).await {
+        Ok(response) => response,
+        Err(e) => error_response(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+async fn proxy(target: Uri, mut request: Request<Body>) -> anyhow::Result<Response> {
+    let mut upstream = Request::builder().method(request.method()).uri(target);
+    for (name, value) in request.headers() {
+        // The runtime trusts whoever holds the URL; credentials stay here.
+        if name != header::HOST && name != header::AUTHORIZATION {
+            upstream = upstream.header(name, value);
+        }
+    }
+    let client_upgrade = hyper::upgrade::on(&mut request);
+    let upstream = upstream.body(std::mem::take(request.body_mut()))?;
+    let mut response = hyper::Client::new().request(upstream).await?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Ok(response.map(body::boxed));
+    }
+    let runtime_upgrade = hyper::upgrade::on(&mut response);
+    tokio::spawn(async move {
+        match tokio::try_join!(client_upgrade, runtime_upgrade) {
+            Ok((mut client, mut runtime)) => {
+                if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut runtime).await {
+                    debug!("streaming connection closed: {}", e);
+                }
+            }
+            Err(e) => warn!("Unable to upgrade streaming connection: {}", e),
+        }
+    });
+    let (parts, _) = response.into_parts();
+    Ok(Response::from_parts(parts, body::boxed(Body::empty())))
+}
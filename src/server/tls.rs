@@ -0,0 +1,173 @@
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum_server::tls_rustls::RustlsConfig;
+use chrono::{Datelike, Utc};
+use rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::kubelet::minikubelet::NODE_NAME;
+use crate::kubelet::{certificate, client};
+use crate::nodemod::address::node_ip;
+
+const SERVING_SIGNER: &str = "kubernetes.io/kubelet-serving";
+const SERVING_CERT_PREFIX: &str = "kubelet-server";
+
+/// Builds server configs around whichever serving certificate is current.
+#[derive(Clone)]
+struct ServerTls {
+    client_ca: Option<RootCertStore>,
+}
+
+impl ServerTls {
+    fn server_config(&self, certs: Vec<Certificate>, key: PrivateKey) -> anyhow::Result<ServerConfig> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        // Anonymous clients are let through here; authorization decides what
+        // they may do.
+        let mut server_config = match &self.client_ca {
+            Some(roots) => builder
+                .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots.clone()).boxed())
+                .with_single_cert(certs, key)?,
+            None => builder.with_no_client_auth().with_single_cert(certs, key)?,
+        };
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(server_config)
+    }
+}
+
+/// TLS settings for the kubelet API. With `serverTLSBootstrap` the serving
+/// certificate is requested from the cluster and swapped in, for new
+/// connections only, whenever it is renewed.
+pub fn rustls_config(kube_config: &kube::Config) -> anyhow::Result<RustlsConfig> {
+    let client_ca = client_ca(kube_config)?;
+    if client_ca.is_none() {
+        if !config().authentication.x509.allow_missing_client_ca {
+            anyhow::bail!(
+                "no client CA configured: set authentication.x509.clientCAFile, or set \
+                 authentication.x509.allowMissingClientCA to serve without client certificate authentication"
+            );
+        }
+        warn!("no client CA configured, client certificates will not be verified");
+    }
+    let tls = ServerTls { client_ca };
+    let (certs, key) = initial_certificate()?;
+    let rustls = RustlsConfig::from_config(Arc::new(tls.server_config(certs, key)?));
+    if config().server_tls_bootstrap {
+        tokio::spawn(rotate_serving_certificate(tls, rustls.clone()));
+    }
+    Ok(rustls)
+}
+
+/// The issued serving certificate, `tlsCertFile`, or a self-signed one when
+/// neither is available.
+fn initial_certificate() -> anyhow::Result<(Vec<Certificate>, PrivateKey)> {
+    let config = config();
+    if config.server_tls_bootstrap {
+        let current = certificate::current_path(Path::new(&config.cert_dir), SERVING_CERT_PREFIX);
+        if certificate::is_valid(&current) {
+            let pem = std::fs::read(&current)?;
+            return Ok((load_certs(&pem)?, load_private_key(&pem)?));
+        }
+        info!("尚无签发的服务证书, 在CSR批准前使用自签名证书");
+        return self_signed();
+    }
+    let (cert_file, key_file) = (Path::new(&config.tls_cert_file), Path::new(&config.tls_private_key_file));
+    if cert_file.exists() && key_file.exists() {
+        return Ok((load_certs(&std::fs::read(cert_file)?)?, load_private_key(&std::fs::read(key_file)?)?));
+    }
+    warn!("{} not found, serving a self-signed certificate", cert_file.display());
+    self_signed()
+}
+
+/// The node name, the host's name and the node IP.
+fn subject_alt_names() -> Vec<rcgen::SanType> {
+    let mut names = vec![rcgen::SanType::DnsName(NODE_NAME.to_string())];
+    if let Ok(hostname) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        let hostname = hostname.trim();
+        if !hostname.is_empty() && hostname != NODE_NAME {
+            names.push(rcgen::SanType::DnsName(hostname.to_string()));
+        }
+    }
+    if let Some(ip) = node_ip() {
+        names.push(rcgen::SanType::IpAddress(ip));
+    }
+    names
+}
+
+/// A one-year self-signed certificate, as the kubelet serves when it has
+/// nothing better.
+fn self_signed() -> anyhow::Result<(Vec<Certificate>, PrivateKey)> {
+    let now = Utc::now();
+    let mut params = rcgen::CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(rcgen::DnType::CommonName, format!("{}@{}", NODE_NAME, now.timestamp()));
+    params.subject_alt_names = subject_alt_names();
+    params.not_before = rcgen::date_time_ymd(now.year(), now.month() as u8, now.day() as u8);
+    params.not_after = rcgen::date_time_ymd(now.year() + 1, now.month() as u8, now.day().min(28) as u8);
+    let cert = rcgen::Certificate::from_params(params)?;
+    Ok((vec![Certificate(cert.serialize_der()?)], PrivateKey(cert.serialize_private_key_der())))
+}
+
+async fn rotate_serving_certificate(tls: ServerTls, rustls: RustlsConfig) {
+    let cert_dir = Path::new(&config().cert_dir);
+    let (tls, rustls) = (&tls, &rustls);
+    certificate::rotate(&certificate::current_path(cert_dir, SERVING_CERT_PREFIX), || async move {
+        let mut params = certificate::node_params();
+        params.subject_alt_names = subject_alt_names();
+        let (cert, key) = certificate::request_certificate(
+            client::client().await?,
+            SERVING_SIGNER,
+            params,
+            &["digital signature", "server auth"],
+        )
+        .await?;
+        certificate::store(cert_dir, SERVING_CERT_PREFIX, &cert, &key).await?;
+        let server_config = tls.server_config(load_certs(cert.as_bytes())?, load_private_key(key.as_bytes())?)?;
+        rustls.reload_from_config(Arc::new(server_config));
+        info!("服务证书已更新");
+        Ok(())
+    })
+    .await;
+}
+
+/// `authentication.x509.clientCAFile`, or the cluster CA from the kubeconfig.
+fn client_ca(kube_config: &kube::Config) -> anyhow::Result<Option<RootCertStore>> {
+    let ca_file = &config().authentication.x509.client_ca_file;
+    let certs = if !ca_file.is_empty() {
+        load_certs(&std::fs::read(ca_file)?)?
+    } else {
+        kube_config.root_cert.clone().unwrap_or_default().into_iter().map(Certificate).collect()
+    };
+    if certs.is_empty() {
+        return Ok(None);
+    }
+    let mut roots = RootCertStore::empty();
+    for cert in &certs {
+        roots.add(cert)?;
+    }
+    Ok(Some(roots))
+}
+
+fn load_certs(pem: &[u8]) -> anyhow::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in PEM data");
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(pem: &[u8]) -> anyhow::Result<PrivateKey> {
+    let mut reader = BufReader::new(pem);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    anyhow::bail!("no private key found in PEM data")
+}
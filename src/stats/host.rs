@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use nix::sys::statvfs::statvfs;
+use tokio::time;
+
+use crate::stats::timestamp;
+use crate::stats::types::{CpuStats, FsStats, InterfaceStats, MemoryStats, NetworkStats, RlimitStats};
+
+/// `/proc/stat` counts in USER_HZ, which is 100 on every Linux platform.
+const NANOS_PER_TICK: u64 = 1_000_000_000 / 100;
+
+/// How often [`sample_cpu`] reads the node CPU counter, as cAdvisor's housekeeping.
+const CPU_HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Node CPU usage in nanoseconds at a time in nanoseconds since the epoch.
+type CpuSample = (i64, u64);
+
+/// The previous and latest node CPU samples. Rates come from
+/// the housekeeping tick, so they do not depend on how often, or by whom,
+/// stats are requested.
+static CPU_SAMPLES: Mutex<(Option<CpuSample>, Option<CpuSample>)> = Mutex::new((None, None));
+
+fn read_proc(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+/// `key: value` files such as `/proc/meminfo`, values in their first unit.
+fn parse_key_values(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':').or_else(|| line.split_once(' '))?;
+            let value = value.split_whitespace().next()?.parse().ok()?;
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// Node boot time in seconds since the epoch.
+pub fn boot_time() -> Option<i64> {
+    read_proc("/proc/stat")?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Cumulative busy CPU time of the node in nanoseconds.
+fn cpu_usage() -> Option<u64> {
+    let stat = read_proc("/proc/stat")?;
+    let fields: Vec<u64> = stat
+        .lines()
+        .find_map(|line| line.strip_prefix("cpu "))?
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    // user, nice, system, idle, iowait, irq, softirq, steal: everything but
+    // idle and iowait is time spent running.
+    let busy: u64 = fields.iter().take(8).enumerate().filter(|(i, _)| *i != 3 && *i != 4).map(|(_, v)| v).sum();
+    Some(busy * NANOS_PER_TICK)
+}
+
+/// Samples the node CPU counter on the housekeeping interval.
+pub async fn sample_cpu() {
+    let mut interval = time::interval(CPU_HOUSEKEEPING_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Some(usage) = cpu_usage() {
+            let now = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+            let mut samples = CPU_SAMPLES.lock().unwrap();
+            *samples = (samples.1, Some((now, usage)));
+        }
+    }
+}
+
+/// The latest housekeeping sample, or a fresh reading without a rate
+/// before the first one.
+pub fn cpu_stats(now: i64) -> Option<CpuStats> {
+    let (previous, latest) = *CPU_SAMPLES.lock().unwrap();
+    let (time, usage) = match latest {
+        Some(sample) => sample,
+        None => (now, cpu_usage()?),
+    };
+    let nano_cores = previous.and_then(|(previous_time, previous_usage)| {
+        let elapsed = (time - previous_time) as u64;
+        (elapsed > 0 && usage >= previous_usage).then(|| (usage - previous_usage) * 1_000_000_000 / elapsed)
+    });
+    Some(CpuStats { time: timestamp(time), usage_nano_cores: nano_cores, usage_core_nano_seconds: Some(usage) })
+}
+
+pub fn memory_stats(now: i64) -> Option<MemoryStats> {
+    let meminfo = parse_key_values(&read_proc("/proc/meminfo")?);
+    let kib = |key: &str| meminfo.get(key).map(|v| v * 1024);
+    let total = kib("MemTotal")?;
+    let usage = total.saturating_sub(kib("MemFree")?);
+    let working_set = usage.saturating_sub(kib("Inactive(file)").unwrap_or_default());
+    let vmstat = read_proc("/proc/vmstat").map(|c| parse_key_values(&c)).unwrap_or_default();
+    Some(MemoryStats {
+        time: timestamp(now),
+        available_bytes: Some(total.saturating_sub(working_set)),
+        usage_bytes: Some(usage),
+        working_set_bytes: Some(working_set),
+        rss_bytes: kib("AnonPages"),
+        page_faults: vmstat.get("pgfault").copied(),
+        major_page_faults: vmstat.get("pgmajfault").copied(),
+    })
+}
+
+/// Interface counters from `/proc/net/dev`; the default interface is the
+/// one carrying the default route.
+pub fn network_stats(now: i64) -> Option<NetworkStats> {
+    let dev = read_proc("/proc/net/dev")?;
+    let interfaces: Vec<InterfaceStats> = dev
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let counters: Vec<u64> = counters.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            Some(InterfaceStats {
+                name: name.trim().to_string(),
+                rx_bytes: counters.first().copied(),
+                rx_errors: counters.get(2).copied(),
+                tx_bytes: counters.get(8).copied(),
+                tx_errors: counters.get(10).copied(),
+            })
+        })
+        .filter(|i| i.name != "lo")
+        .collect();
+    let default_name = read_proc("/proc/net/route").and_then(|route| {
+        route.lines().skip(1).find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            (fields.next()? == "00000000").then(|| name.to_string())
+        })
+    });
+    let default_interface = interfaces
+        .iter()
+        .find(|i| Some(&i.name) == default_name.as_ref())
+        .or_else(|| interfaces.first())
+        .cloned()?;
+    Some(NetworkStats { time: timestamp(now), default_interface, interfaces })
+}
+
+/// Capacity and usage of the filesystem holding `path`.
+pub fn fs_stats(path: &Path, now: i64) -> Option<FsStats> {
+    let vfs = statvfs(path).ok()?;
+    let block = vfs.fragment_size() as u64;
+    let (blocks, free, available) = (vfs.blocks() as u64, vfs.blocks_free() as u64, vfs.blocks_available() as u64);
+    let (inodes, inodes_free) = (vfs.files() as u64, vfs.files_free() as u64);
+    Some(FsStats {
+        time: timestamp(now),
+        available_bytes: Some(available * block),
+        capacity_bytes: Some(blocks * block),
+        used_bytes: Some((blocks - free) * block),
+        inodes_free: Some(inodes_free),
+        inodes: Some(inodes),
+        inodes_used: Some(inodes - inodes_free),
+    })
+}
+
+/// [`dir_usage`] on the blocking pool, so walking large trees does not stall
+/// the runtime.
+pub async fn dir_usage_blocking(path: PathBuf) -> (u64, u64) {
+    tokio::task::spawn_blocking(move || dir_usage(&path)).await.unwrap_or_default()
+}
+
+/// Bytes and inodes used under a directory.
+pub fn dir_usage(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    let (mut bytes, mut inodes) = (metadata.len(), 1);
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+            let (b, i) = dir_usage(&entry.path());
+            bytes += b;
+            inodes += i;
+        }
+    }
+    (bytes, inodes)
+}
+
+pub fn rlimit_stats(now: i64) -> RlimitStats {
+    let maxpid = read_proc("/proc/sys/kernel/pid_max").and_then(|v| v.trim().parse().ok());
+    let curproc = std::fs::read_dir("/proc").ok().map(|entries| {
+        entries
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+            .count() as u64
+    });
+    RlimitStats { time: timestamp(now), maxpid, curproc }
+}
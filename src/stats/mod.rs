@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{SecondsFormat, Utc};
+use tracing::*;
+
+use crate::kubelet::config::config;
+use crate::kubelet::minikubelet::NODE_NAME;
+use crate::logs;
+use crate::provider::{cri, get_client, get_image_client};
+use crate::stats::types::*;
+
+mod host;
+pub mod types;
+
+pub use host::sample_cpu;
+
+/// RFC 3339 time with milliseconds, which `/metrics/resource` stamps samples with.
+pub fn timestamp(nanos: i64) -> String {
+    chrono::DateTime::from_timestamp_nanos(nanos).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Builds the Summary API from the runtime's container and sandbox stats
+/// and the host's `/proc` data.
+pub async fn summary(only_cpu_and_memory: bool) -> anyhow::Result<Summary> {
+    let now = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let image_fs = image_fs_stats(now).await;
+    let node = NodeStats {
+        node_name: NODE_NAME.to_string(),
+        start_time: host::boot_time().map(|s| timestamp(s * 1_000_000_000)).unwrap_or_default(),
+        cpu: host::cpu_stats(now),
+        memory: host::memory_stats(now),
+        network: host::network_stats(now),
+        fs: host::fs_stats(Path::new(&config().root_dir), now),
+        runtime: Some(RuntimeStats { image_fs: image_fs.clone() }),
+        rlimit: Some(host::rlimit_stats(now)),
+    };
+    let pods = pod_stats(now, image_fs.as_ref()).await?;
+    let mut summary = Summary { node, pods };
+    if only_cpu_and_memory {
+        strip_to_cpu_and_memory(&mut summary);
+    }
+    Ok(summary)
+}
+
+fn strip_to_cpu_and_memory(summary: &mut Summary) {
+    let node = &mut summary.node;
+    (node.network, node.fs, node.runtime, node.rlimit) = (None, None, None, None);
+    for pod in &mut summary.pods {
+        pod.network = None;
+        pod.volume.clear();
+        pod.ephemeral_storage = None;
+        pod.process_stats = None;
+        for container in &mut pod.containers {
+            (container.rootfs, container.logs) = (None, None);
+        }
+    }
+}
+
+/// The runtime's image filesystem: usage from `ImageFsInfo`, capacity from
+/// the filesystem it lives on.
+async fn image_fs_stats(now: i64) -> Option<FsStats> {
+    let response = match get_image_client().await.image_fs_info(cri::ImageFsInfoRequest::default()).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            warn!("获取镜像文件系统信息失败: {}", e);
+            return None;
+        }
+    };
+    let usage = response.image_filesystems.into_iter().next()?;
+    let mountpoint = usage.fs_id.as_ref().map(|id| id.mountpoint.clone()).unwrap_or_default();
+    Some(fs_usage(&usage, host::fs_stats(Path::new(&mountpoint), now).as_ref()))
+}
+
+async fn pod_stats(now: i64, image_fs: Option<&FsStats>) -> anyhow::Result<Vec<PodStats>> {
+    let filter = cri::PodSandboxFilter {
+        state: Some(cri::PodSandboxStateValue { state: cri::PodSandboxState::SandboxReady as i32 }),
+        ..Default::default()
+    };
+    let sandboxes = get_client().await
+        .list_pod_sandbox(cri::ListPodSandboxRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .items;
+    let filter = cri::ContainerFilter {
+        state: Some(cri::ContainerStateValue { state: cri::ContainerState::ContainerRunning as i32 }),
+        ..Default::default()
+    };
+    let containers = get_client().await
+        .list_containers(cri::ListContainersRequest { filter: Some(filter) })
+        .await?
+        .into_inner()
+        .containers;
+    let container_stats: HashMap<String, cri::ContainerStats> = get_client().await
+        .list_container_stats(cri::ListContainerStatsRequest::default())
+        .await?
+        .into_inner()
+        .stats
+        .into_iter()
+        .filter_map(|s| Some((s.attributes.as_ref()?.id.clone(), s)))
+        .collect();
+    // Not every runtime implements sandbox stats; pods then add up their containers.
+    let sandbox_stats: HashMap<String, cri::LinuxPodSandboxStats> = match get_client().await
+        .list_pod_sandbox_stats(cri::ListPodSandboxStatsRequest::default())
+        .await
+    {
+        Ok(response) => response
+            .into_inner()
+            .stats
+            .into_iter()
+            .filter_map(|s| Some((s.attributes?.id, s.linux?)))
+            .collect(),
+        Err(e) => {
+            debug!("ListPodSandboxStats unavailable: {}", e);
+            HashMap::new()
+        }
+    };
+    let log_fs = host::fs_stats(Path::new(logs::POD_LOGS_ROOT), now);
+    let root_fs = host::fs_stats(Path::new(&config().root_dir), now);
+
+    let mut pods = vec![];
+    for sandbox in sandboxes {
+        let metadata = sandbox.metadata.clone().unwrap_or_default();
+        let uid = metadata.uid.clone();
+        let pod_log_dir = logs::pod_log_dir(&metadata.namespace, &metadata.name, &uid);
+        let mut pod_containers: Vec<ContainerStats> = vec![];
+        for c in containers.iter().filter(|c| c.pod_sandbox_id == sandbox.id) {
+            let name = c.metadata.as_ref().map(|m| m.name.clone()).unwrap_or_default();
+            let stats = container_stats.get(&c.id);
+            let (log_bytes, log_inodes) = host::dir_usage_blocking(pod_log_dir.join(&name)).await;
+            pod_containers.push(ContainerStats {
+                start_time: timestamp(c.created_at),
+                cpu: stats.and_then(|s| s.cpu.as_ref()).map(cpu),
+                memory: stats.and_then(|s| s.memory.as_ref()).map(memory),
+                rootfs: stats.and_then(|s| s.writable_layer.as_ref()).map(|fs| fs_usage(fs, image_fs)),
+                logs: Some(with_usage(log_fs.as_ref(), now, log_bytes, log_inodes)),
+                name,
+            });
+        }
+        let containers = pod_containers;
+        let linux = sandbox_stats.get(&sandbox.id);
+        let volume = volume_stats(&uid, now).await;
+        // Ephemeral storage is what the pod writes locally: container
+        // writable layers, logs and local volumes.
+        let (ephemeral_used, ephemeral_inodes) = containers
+            .iter()
+            .flat_map(|c| [c.rootfs.as_ref(), c.logs.as_ref()])
+            .chain(volume.iter().map(|v| Some(&v.fs)))
+            .flatten()
+            .fold((0, 0), |(bytes, inodes), fs| {
+                (bytes + fs.used_bytes.unwrap_or_default(), inodes + fs.inodes_used.unwrap_or_default())
+            });
+        pods.push(PodStats {
+            pod_ref: PodReference { name: metadata.name, namespace: metadata.namespace, uid },
+            start_time: timestamp(sandbox.created_at),
+            cpu: linux.and_then(|l| l.cpu.as_ref()).map(cpu).or_else(|| sum_cpu(&containers, now)),
+            memory: linux.and_then(|l| l.memory.as_ref()).map(memory).or_else(|| sum_memory(&containers, now)),
+            network: linux.and_then(|l| l.network.as_ref()).and_then(network),
+            process_stats: linux
+                .and_then(|l| l.process.as_ref())
+                .map(|p| ProcessStats { process_count: p.process_count.as_ref().map(|v| v.value) }),
+            ephemeral_storage: Some(with_usage(root_fs.as_ref(), now, ephemeral_used, ephemeral_inodes)),
+            volume,
+            containers,
+        });
+    }
+    Ok(pods)
+}
+
+/// Local volumes under `<podDir>/volumes/<plugin>/<name>`, walked on the
+/// blocking pool.
+async fn volume_stats(pod_uid: &str, now: i64) -> Vec<VolumeStats> {
+    let volumes_dir = config().pod_dir(pod_uid).join("volumes");
+    let walk = move || {
+        let capacity = host::fs_stats(&volumes_dir, now);
+        let mut volumes = vec![];
+        for plugin in std::fs::read_dir(&volumes_dir).into_iter().flatten().flatten() {
+            for volume in std::fs::read_dir(plugin.path()).into_iter().flatten().flatten() {
+                let (bytes, inodes) = host::dir_usage(&volume.path());
+                volumes.push(VolumeStats {
+                    fs: with_usage(capacity.as_ref(), now, bytes, inodes),
+                    name: volume.file_name().to_string_lossy().into_owned(),
+                });
+            }
+        }
+        volumes
+    };
+    tokio::task::spawn_blocking(walk).await.unwrap_or_default()
+}
+
+/// Usage measured by the kubelet on a filesystem of known capacity.
+fn with_usage(capacity: Option<&FsStats>, now: i64, used_bytes: u64, inodes_used: u64) -> FsStats {
+    FsStats {
+        time: timestamp(now),
+        used_bytes: Some(used_bytes),
+        inodes_used: Some(inodes_used),
+        ..capacity.cloned().unwrap_or_default()
+    }
+}
+
+fn fs_usage(usage: &cri::FilesystemUsage, capacity: Option<&FsStats>) -> FsStats {
+    FsStats {
+        time: timestamp(usage.timestamp),
+        used_bytes: usage.used_bytes.as_ref().map(|v| v.value),
+        inodes_used: usage.inodes_used.as_ref().map(|v| v.value),
+        ..capacity.cloned().unwrap_or_default()
+    }
+}
+
+fn cpu(usage: &cri::CpuUsage) -> CpuStats {
+    CpuStats {
+        time: timestamp(usage.timestamp),
+        usage_nano_cores: usage.usage_nano_cores.as_ref().map(|v| v.value),
+        usage_core_nano_seconds: usage.usage_core_nano_seconds.as_ref().map(|v| v.value),
+    }
+}
+
+fn memory(usage: &cri::MemoryUsage) -> MemoryStats {
+    MemoryStats {
+        time: timestamp(usage.timestamp),
+        available_bytes: usage.available_bytes.as_ref().map(|v| v.value),
+        usage_bytes: usage.usage_bytes.as_ref().map(|v| v.value),
+        working_set_bytes: usage.working_set_bytes.as_ref().map(|v| v.value),
+        rss_bytes: usage.rss_bytes.as_ref().map(|v| v.value),
+        page_faults: usage.page_faults.as_ref().map(|v| v.value),
+        major_page_faults: usage.major_page_faults.as_ref().map(|v| v.value),
+    }
+}
+
+fn interface(usage: &cri::NetworkInterfaceUsage) -> InterfaceStats {
+    InterfaceStats {
+        name: usage.name.clone(),
+        rx_bytes: usage.rx_bytes.as_ref().map(|v| v.value),
+        rx_errors: usage.rx_errors.as_ref().map(|v| v.value),
+        tx_bytes: usage.tx_bytes.as_ref().map(|v| v.value),
+        tx_errors: usage.tx_errors.as_ref().map(|v| v.value),
+    }
+}
+
+fn network(usage: &cri::NetworkUsage) -> Option<NetworkStats> {
+    let default_interface = interface(usage.default_interface.as_ref()?);
+    let mut interfaces = vec![default_interface.clone()];
+    interfaces.extend(usage.interfaces.iter().map(interface));
+    Some(NetworkStats { time: timestamp(usage.timestamp), default_interface, interfaces })
+}
+
+fn sum(values: impl Iterator<Item = Option<u64>>) -> Option<u64> {
+    values.fold(None, |total, v| match (total, v) {
+        (total, None) => total,
+        (total, Some(v)) => Some(total.unwrap_or_default() + v),
+    })
+}
+
+fn sum_cpu(containers: &[ContainerStats], now: i64) -> Option<CpuStats> {
+    let cpus: Vec<&CpuStats> = containers.iter().filter_map(|c| c.cpu.as_ref()).collect();
+    if cpus.is_empty() {
+        return None;
+    }
+    Some(CpuStats {
+        time: timestamp(now),
+        usage_nano_cores: sum(cpus.iter().map(|c| c.usage_nano_cores)),
+        usage_core_nano_seconds: sum(cpus.iter().map(|c| c.usage_core_nano_seconds)),
+    })
+}
+
+fn sum_memory(containers: &[ContainerStats], now: i64) -> Option<MemoryStats> {
+    let memories: Vec<&MemoryStats> = containers.iter().filter_map(|c| c.memory.as_ref()).collect();
+    if memories.is_empty() {
+        return None;
+    }
+    Some(MemoryStats {
+        time: timestamp(now),
+        available_bytes: None,
+        usage_bytes: sum(memories.iter().map(|m| m.usage_bytes)),
+        working_set_bytes: sum(memories.iter().map(|m| m.working_set_bytes)),
+        rss_bytes: sum(memories.iter().map(|m| m.rss_bytes)),
+        page_faults: sum(memories.iter().map(|m| m.page_faults)),
+        major_page_faults: sum(memories.iter().map(|m| m.major_page_faults)),
+    })
+}